@@ -0,0 +1,57 @@
+-- Types, domains and tables. Column order matters: the From<&Row> impls
+-- read columns by index, see e.g. Account::from.
+
+CREATE TYPE "Rank" AS ENUM (
+    'None',
+    'Member',
+    'Moderator',
+    'Admin',
+    'Owner'
+);
+
+CREATE DOMAIN username AS VARCHAR(255) CHECK (value ~* '^[a-zA-Z0-9_]{3,}$'); -- Checks if the name has no symbols except underscore and at least 3 characters.
+CREATE DOMAIN email AS VARCHAR(254) CHECK (value ~* '^[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}$'); -- Checks if it is a valid email.
+
+CREATE TABLE accounts (
+    id VARCHAR(255) PRIMARY KEY,
+    username username NOT NULL UNIQUE,
+    email email NOT NULL UNIQUE,
+    password VARCHAR(255) NOT NULL,
+    rank public."Rank" NOT NULL,
+    banned BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE TABLE sessions (
+    session_id VARCHAR(255) PRIMARY KEY,
+    account_id VARCHAR(255) NOT NULL REFERENCES accounts(id),
+    expires_in VARCHAR(255) NOT NULL,
+    id VARCHAR(255) NOT NULL UNIQUE,
+    user_agent VARCHAR(512),
+    ip VARCHAR(64),
+    created_at VARCHAR(255) NOT NULL,
+    last_seen VARCHAR(255) NOT NULL,
+    refresh_hash VARCHAR(64) UNIQUE,
+    refresh_expires_in VARCHAR(255)
+);
+
+CREATE TABLE access_tokens (
+    id VARCHAR(255) PRIMARY KEY,
+    account_id VARCHAR(255) NOT NULL REFERENCES accounts(id),
+    name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at VARCHAR(255) NOT NULL,
+    last_used VARCHAR(255),
+    expires_in VARCHAR(255)
+);
+
+CREATE TABLE threads (
+    id VARCHAR(255) PRIMARY KEY,
+    title VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    created_by VARCHAR(255) REFERENCES accounts(id),
+    created_on VARCHAR(255) NOT NULL,
+    updated_on VARCHAR(255),
+    deleted_on VARCHAR(255)
+);
@@ -0,0 +1,377 @@
+-- Every query the server runs goes through one of these.
+
+CREATE OR REPLACE FUNCTION is_username_taken(target_username VARCHAR) RETURNS BOOLEAN
+AS $$
+BEGIN 
+	IF EXISTS(SELECT 1 from accounts WHERE username = target_username) = true THEN
+		RAISE EXCEPTION 'The username % is already taken', target_username USING ERRCODE = '42P10';
+		RETURN TRUE;
+	END IF;
+	RETURN FALSE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION is_email_taken(target_email VARCHAR) RETURNS BOOLEAN
+AS $$
+BEGIN 
+	IF EXISTS(SELECT 1 from accounts WHERE email = target_email) = true THEN
+		RAISE EXCEPTION 'The email % is already taken', target_email USING ERRCODE = '42P11';
+		RETURN TRUE;
+	END IF;
+	RETURN FALSE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION create_account(id varchar, username varchar, email varchar, password varchar, rank public."Rank")
+RETURNS BOOLEAN
+AS $$
+BEGIN
+	IF is_username_taken(username) THEN
+		RETURN FALSE;
+	END IF;
+	IF is_email_taken(email) THEN
+		RETURN FALSE;
+	END IF;
+	INSERT INTO accounts (id, username, email, password, rank) VALUES(id, username, email, password, rank);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+
+CREATE OR REPLACE FUNCTION find_by_id(target_id VARCHAR) 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	PERFORM 1 from accounts WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_by_username(target_username VARCHAR) 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	PERFORM 1 from accounts WHERE accounts.username = target_username;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with name %', target_username USING ERRCODE = '42P13';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.username = target_username;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_by_email(target_email VARCHAR) 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	PERFORM 1 from accounts WHERE accounts.email = target_email;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with email %', target_email USING ERRCODE = '42P14';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.email = target_email;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_by_session(target_sess_id VARCHAR) 
+	RETURNS setof accounts
+AS $$
+DECLARE acc_id varchar := '';
+BEGIN 
+	SELECT account_id INTO acc_id FROM sessions WHERE sessions.session_id = target_sess_id;
+	IF FOUND THEN 
+			RETURN QUERY SELECT * from accounts WHERE id = acc_id;
+		ELSE
+			RAISE EXCEPTION 'No session found with id %', target_sess_id USING ERRCODE = '42P15';
+	END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+
+CREATE OR REPLACE FUNCTION create_session(sess_id varchar, acc_id varchar, expires varchar, public_id varchar, agent varchar, ip_addr varchar, created varchar, refresh varchar, refresh_expires varchar)
+RETURNS BOOLEAN
+AS $$
+BEGIN
+	INSERT INTO sessions (session_id, account_id, expires_in, id, user_agent, ip, created_at, last_seen, refresh_hash, refresh_expires_in) 
+		VALUES(sess_id, acc_id, expires, public_id, agent, ip_addr, created, created, refresh, refresh_expires);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_session_by_refresh(target_refresh VARCHAR) 
+	RETURNS setof sessions
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM sessions WHERE sessions.refresh_hash = target_refresh;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION rotate_session(old_refresh VARCHAR, sess_id VARCHAR, expires VARCHAR, refresh VARCHAR, refresh_expires VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE sessions SET 
+		session_id = sess_id, 
+		expires_in = expires, 
+		refresh_hash = refresh, 
+		refresh_expires_in = refresh_expires
+	WHERE sessions.refresh_hash = old_refresh;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No session found for this refresh token' USING ERRCODE = '42P15';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_sessions(acc_id VARCHAR) 
+	RETURNS setof sessions
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM sessions WHERE sessions.account_id = acc_id ORDER BY sessions.last_seen DESC;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION revoke_session(target_sess_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM sessions WHERE sessions.session_id = target_sess_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_session(acc_id VARCHAR, public_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM sessions WHERE sessions.account_id = acc_id AND sessions.id = public_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No session found with id %', public_id USING ERRCODE = '42P15';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_other_sessions(acc_id VARCHAR, keep_sess_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM sessions WHERE sessions.account_id = acc_id AND sessions.session_id <> keep_sess_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION touch_session(target_sess_id VARCHAR, seen VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE sessions SET last_seen = seen WHERE sessions.session_id = target_sess_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_session(target_sess_id VARCHAR) 
+	RETURNS setof sessions
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM sessions WHERE sessions.session_id = target_sess_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION create_thread(id varchar, title varchar, body varchar, creator varchar, creation varchar)
+RETURNS BOOLEAN
+AS $$
+BEGIN
+	INSERT INTO threads (id, title, body, created_by, created_on) VALUES(id, title, body, creator, creation);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_thread_by_id(target_id VARCHAR) 
+	RETURNS setof threads
+AS $$
+BEGIN 
+	PERFORM 1 from threads WHERE threads.id = target_id AND threads.deleted_on IS NULL;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No thread found with id %', target_id USING ERRCODE = '42P16';
+	END IF;
+	RETURN QUERY SELECT * FROM threads WHERE threads.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_threads(creator VARCHAR, title_query VARCHAR, lim BIGINT, off BIGINT) 
+	RETURNS setof threads
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM threads 
+		WHERE threads.deleted_on IS NULL
+		AND (creator IS NULL OR threads.created_by = creator)
+		AND (title_query IS NULL OR position(lower(title_query) in lower(threads.title)) > 0)
+		ORDER BY threads.created_on DESC
+		LIMIT lim OFFSET off;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION update_thread(target_id VARCHAR, new_title VARCHAR, new_body VARCHAR, updated VARCHAR) 
+	RETURNS setof threads
+AS $$
+BEGIN 
+	UPDATE threads SET 
+		title = COALESCE(new_title, threads.title), 
+		body = COALESCE(new_body, threads.body), 
+		updated_on = updated
+	WHERE threads.id = target_id AND threads.deleted_on IS NULL;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No thread found with id %', target_id USING ERRCODE = '42P16';
+	END IF;
+	RETURN QUERY SELECT * FROM threads WHERE threads.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_thread(target_id VARCHAR, deleted VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE threads SET deleted_on = deleted WHERE threads.id = target_id AND threads.deleted_on IS NULL;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No thread found with id %', target_id USING ERRCODE = '42P16';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION purge_thread(target_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM threads WHERE threads.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No thread found with id %', target_id USING ERRCODE = '42P16';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION restore_thread(target_id VARCHAR) 
+	RETURNS setof threads
+AS $$
+BEGIN 
+	UPDATE threads SET deleted_on = NULL WHERE threads.id = target_id AND threads.deleted_on IS NOT NULL;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No deleted thread found with id %', target_id USING ERRCODE = '42P16';
+	END IF;
+	RETURN QUERY SELECT * FROM threads WHERE threads.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION update_account(target_id VARCHAR, new_username VARCHAR, new_email VARCHAR) 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	IF new_username IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND username = new_username) THEN
+		PERFORM is_username_taken(new_username);
+	END IF;
+	IF new_email IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND email = new_email) THEN
+		PERFORM is_email_taken(new_email);
+	END IF;
+	UPDATE accounts SET 
+		username = COALESCE(new_username, accounts.username), 
+		email = COALESCE(new_email, accounts.email)
+	WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_account(target_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM sessions WHERE sessions.account_id = target_id;
+	DELETE FROM access_tokens WHERE access_tokens.account_id = target_id;
+	UPDATE threads SET created_by = NULL WHERE threads.created_by = target_id;
+	DELETE FROM accounts WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION set_rank(target_id VARCHAR, new_rank public."Rank") 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	UPDATE accounts SET rank = new_rank WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION set_banned(target_id VARCHAR, is_banned BOOLEAN) 
+	RETURNS setof accounts
+AS $$
+BEGIN 
+	UPDATE accounts SET banned = is_banned WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	IF is_banned THEN
+		DELETE FROM sessions WHERE sessions.account_id = target_id;
+		DELETE FROM access_tokens WHERE access_tokens.account_id = target_id;
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION create_access_token(token_id VARCHAR, acc_id VARCHAR, token_name VARCHAR, hash VARCHAR, token_scopes TEXT[], created VARCHAR, expires VARCHAR)
+RETURNS BOOLEAN
+AS $$
+BEGIN
+	INSERT INTO access_tokens (id, account_id, name, token_hash, scopes, created_at, expires_in) 
+		VALUES(token_id, acc_id, token_name, hash, token_scopes, created, expires);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_access_token(hash VARCHAR) 
+	RETURNS setof access_tokens
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM access_tokens WHERE access_tokens.token_hash = hash;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_access_tokens(acc_id VARCHAR) 
+	RETURNS setof access_tokens
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM access_tokens WHERE access_tokens.account_id = acc_id ORDER BY access_tokens.created_at DESC;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_access_token(acc_id VARCHAR, token_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM access_tokens WHERE access_tokens.account_id = acc_id AND access_tokens.id = token_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No access token found with id %', token_id USING ERRCODE = '42P17';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION touch_access_token(token_id VARCHAR, used VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE access_tokens SET last_used = used WHERE access_tokens.id = token_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
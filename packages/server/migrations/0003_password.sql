@@ -0,0 +1,14 @@
+-- Lets a password hash be replaced, e.g. when it's upgraded to the
+-- current algorithm on login.
+
+CREATE OR REPLACE FUNCTION set_password(target_id VARCHAR, new_password VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE accounts SET password = new_password WHERE accounts.id = target_id;
+	IF NOT FOUND THEN 
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,56 @@
+-- Single use tokens that are mailed to an account (password resets, ..).
+-- Column order matters, see OneTimeToken::from.
+
+CREATE TABLE one_time_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    account_id VARCHAR(255) NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    purpose VARCHAR(64) NOT NULL,
+    created_at VARCHAR(255) NOT NULL,
+    expires_in VARCHAR(255) NOT NULL
+);
+
+CREATE OR REPLACE FUNCTION create_one_time_token(hash VARCHAR, acc_id VARCHAR, token_purpose VARCHAR, created VARCHAR, expires VARCHAR)
+RETURNS BOOLEAN
+AS $$
+BEGIN
+	INSERT INTO one_time_tokens (token_hash, account_id, purpose, created_at, expires_in) 
+		VALUES(hash, acc_id, token_purpose, created, expires);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION find_one_time_token(hash VARCHAR, token_purpose VARCHAR) 
+	RETURNS setof one_time_tokens
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM one_time_tokens 
+		WHERE one_time_tokens.token_hash = hash AND one_time_tokens.purpose = token_purpose;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Returns the token it deleted, nothing if someone else redeemed it first.
+CREATE OR REPLACE FUNCTION consume_one_time_token(hash VARCHAR) 
+	RETURNS setof one_time_tokens
+AS $$
+BEGIN 
+	RETURN QUERY DELETE FROM one_time_tokens WHERE one_time_tokens.token_hash = hash RETURNING *;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_one_time_tokens(acc_id VARCHAR, token_purpose VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM one_time_tokens WHERE one_time_tokens.account_id = acc_id AND one_time_tokens.purpose = token_purpose;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_sessions(acc_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM sessions WHERE sessions.account_id = acc_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,13 @@
+-- New accounts start at the 'None' rank until their email is confirmed,
+-- resending the confirmation mail is throttled by the latest token.
+
+CREATE OR REPLACE FUNCTION find_latest_one_time_token(acc_id VARCHAR, token_purpose VARCHAR) 
+	RETURNS setof one_time_tokens
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM one_time_tokens 
+		WHERE one_time_tokens.account_id = acc_id AND one_time_tokens.purpose = token_purpose
+		ORDER BY one_time_tokens.created_at::NUMERIC DESC
+		LIMIT 1;
+END;
+$$ LANGUAGE plpgsql;
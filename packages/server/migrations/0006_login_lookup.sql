@@ -0,0 +1,5 @@
+-- Logins match the username or email case insensitive but exactly,
+-- these keep that lookup from scanning the whole table.
+
+CREATE INDEX accounts_username_lower_idx ON accounts (lower(username));
+CREATE INDEX accounts_email_lower_idx ON accounts (lower(email));
@@ -0,0 +1,43 @@
+-- Failed logins per account and per ip, see account::lockout.
+-- Column order matters, see LoginAttempts::from.
+
+CREATE TABLE login_attempts (
+    attempt_key VARCHAR(255) PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure VARCHAR(255) NOT NULL
+);
+
+CREATE OR REPLACE FUNCTION find_login_attempts(a_key VARCHAR) 
+	RETURNS setof login_attempts
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM login_attempts WHERE login_attempts.attempt_key = a_key;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Counts a failure in one statement so parallel guesses can't skip any,
+-- failures older than reset_after (in ms) start over.
+CREATE OR REPLACE FUNCTION record_login_failure(a_key VARCHAR, failed_at VARCHAR, reset_after VARCHAR) 
+	RETURNS setof login_attempts
+AS $$
+BEGIN 
+	RETURN QUERY INSERT INTO login_attempts AS attempts (attempt_key, failures, last_failure) 
+		VALUES (a_key, 1, failed_at)
+		ON CONFLICT (attempt_key) DO UPDATE SET
+			failures = CASE 
+				WHEN failed_at::NUMERIC - attempts.last_failure::NUMERIC > reset_after::NUMERIC THEN 1 
+				ELSE attempts.failures + 1 
+			END,
+			last_failure = failed_at
+		RETURNING *;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION clear_login_attempts(a_key VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM login_attempts WHERE login_attempts.attempt_key = a_key;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
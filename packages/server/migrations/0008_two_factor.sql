@@ -0,0 +1,73 @@
+-- TOTP secrets and recovery codes, see account::twofactor.
+-- Column order matters, see TwoFactor::from.
+
+CREATE TABLE two_factor (
+    account_id VARCHAR(255) PRIMARY KEY REFERENCES accounts(id) ON DELETE CASCADE,
+    secret VARCHAR(64) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    last_step BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE recovery_codes (
+    account_id VARCHAR(255) NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    PRIMARY KEY (account_id, code_hash)
+);
+
+CREATE OR REPLACE FUNCTION find_two_factor(acc_id VARCHAR) 
+	RETURNS setof two_factor
+AS $$
+BEGIN 
+	RETURN QUERY SELECT * FROM two_factor WHERE two_factor.account_id = acc_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION save_two_factor(acc_id VARCHAR, new_secret VARCHAR, is_enabled BOOLEAN, step BIGINT) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	INSERT INTO two_factor (account_id, secret, enabled, last_step) VALUES (acc_id, new_secret, is_enabled, step)
+		ON CONFLICT (account_id) DO UPDATE SET secret = new_secret, enabled = is_enabled, last_step = step;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Only true for the first request using a time step, so a code can't
+-- be replayed.
+CREATE OR REPLACE FUNCTION use_two_factor_step(acc_id VARCHAR, step BIGINT) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	UPDATE two_factor SET last_step = step WHERE two_factor.account_id = acc_id AND two_factor.last_step < step;
+	RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_two_factor(acc_id VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM recovery_codes WHERE recovery_codes.account_id = acc_id;
+	DELETE FROM two_factor WHERE two_factor.account_id = acc_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION set_recovery_codes(acc_id VARCHAR, hashes VARCHAR[]) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM recovery_codes WHERE recovery_codes.account_id = acc_id;
+	INSERT INTO recovery_codes (account_id, code_hash) SELECT acc_id, unnest(hashes);
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION use_recovery_code(acc_id VARCHAR, hash VARCHAR) 
+	RETURNS BOOLEAN
+AS $$
+BEGIN 
+	DELETE FROM recovery_codes WHERE recovery_codes.account_id = acc_id AND recovery_codes.code_hash = hash;
+	RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql;
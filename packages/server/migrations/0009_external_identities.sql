@@ -0,0 +1,32 @@
+-- Accounts linked to an OpenID Connect provider, see account::oidc.
+-- Column order matters, see ExternalIdentity::from.
+
+CREATE TABLE external_identities (
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    account_id VARCHAR(255) NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    linked_at VARCHAR(255) NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
+
+CREATE INDEX external_identities_account_id ON external_identities (account_id);
+
+CREATE OR REPLACE FUNCTION find_external_identity(iss VARCHAR, sub VARCHAR)
+	RETURNS setof external_identities
+AS $$
+BEGIN
+	RETURN QUERY SELECT * FROM external_identities
+		WHERE external_identities.issuer = iss AND external_identities.subject = sub;
+END;
+$$ LANGUAGE plpgsql;
+
+-- An identity stays with the account it was linked to first.
+CREATE OR REPLACE FUNCTION link_external_identity(iss VARCHAR, sub VARCHAR, acc_id VARCHAR, linked VARCHAR)
+	RETURNS BOOLEAN
+AS $$
+BEGIN
+	INSERT INTO external_identities (issuer, subject, account_id, linked_at) VALUES (iss, sub, acc_id, linked)
+		ON CONFLICT (issuer, subject) DO NOTHING;
+	RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql;
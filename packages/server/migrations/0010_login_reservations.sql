@@ -0,0 +1,49 @@
+-- Login attempts are counted before the password is checked, in the same
+-- step as the wait is checked, see LoginLimiter. Replaces the separate
+-- lookup and record_login_failure, which parallel guesses could slip
+-- between.
+
+DROP FUNCTION IF EXISTS find_login_attempts(VARCHAR);
+DROP FUNCTION IF EXISTS record_login_failure(VARCHAR, VARCHAR, VARCHAR);
+
+-- Counts an attempt unless the key still has to wait. Answers with the
+-- failures counted so far and the seconds left to wait, 0 if the attempt
+-- was counted. The wait is LoginAttempts::delay, failures older than
+-- reset_after (in ms) start over.
+CREATE OR REPLACE FUNCTION reserve_login_attempt(a_key VARCHAR, attempted_at VARCHAR, free INTEGER, base_delay BIGINT, max_delay BIGINT, reset_after VARCHAR)
+	RETURNS TABLE (total_failures INTEGER, wait_secs BIGINT)
+AS $$
+DECLARE
+	attempts login_attempts;
+	wait_ms NUMERIC;
+BEGIN
+	INSERT INTO login_attempts (attempt_key, failures, last_failure) VALUES (a_key, 0, attempted_at)
+		ON CONFLICT (attempt_key) DO NOTHING;
+	SELECT * INTO attempts FROM login_attempts WHERE login_attempts.attempt_key = a_key FOR UPDATE;
+	IF attempted_at::NUMERIC - attempts.last_failure::NUMERIC > reset_after::NUMERIC THEN
+		attempts.failures := 0;
+	END IF;
+	IF attempts.failures >= free THEN
+		wait_ms := attempts.last_failure::NUMERIC - attempted_at::NUMERIC
+			+ LEAST(max_delay, base_delay * power(2::NUMERIC, LEAST(attempts.failures - free, 60))) * 1000;
+		IF wait_ms > 0 THEN
+			RETURN QUERY SELECT attempts.failures, CEIL(wait_ms / 1000)::BIGINT;
+			RETURN;
+		END IF;
+	END IF;
+	UPDATE login_attempts SET failures = attempts.failures + 1, last_failure = attempted_at
+		WHERE login_attempts.attempt_key = a_key;
+	RETURN QUERY SELECT attempts.failures + 1, 0::BIGINT;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Takes back an attempt once its password turned out right.
+CREATE OR REPLACE FUNCTION release_login_attempt(a_key VARCHAR)
+	RETURNS BOOLEAN
+AS $$
+BEGIN
+	UPDATE login_attempts SET failures = GREATEST(login_attempts.failures - 1, 0)
+		WHERE login_attempts.attempt_key = a_key;
+	RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,11 @@
+-- Access tokens go with the sessions once the password of their account
+-- changed or was reset.
+
+CREATE OR REPLACE FUNCTION delete_access_tokens(acc_id VARCHAR)
+	RETURNS BOOLEAN
+AS $$
+BEGIN
+	DELETE FROM access_tokens WHERE access_tokens.account_id = acc_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
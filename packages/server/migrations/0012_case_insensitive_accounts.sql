@@ -0,0 +1,53 @@
+-- Usernames and emails are unique regardless of case, logins already
+-- match them that way. The unique indexes replace the lookup ones of
+-- 0006. Accounts differing only in case have to be renamed first.
+
+DROP INDEX IF EXISTS accounts_username_lower_idx;
+DROP INDEX IF EXISTS accounts_email_lower_idx;
+
+CREATE UNIQUE INDEX accounts_username_lower_key ON accounts (lower(username));
+CREATE UNIQUE INDEX accounts_email_lower_key ON accounts (lower(email));
+
+CREATE OR REPLACE FUNCTION is_username_taken(target_username VARCHAR) RETURNS BOOLEAN
+AS $$
+BEGIN
+	IF EXISTS(SELECT 1 from accounts WHERE lower(username) = lower(target_username)) = true THEN
+		RAISE EXCEPTION 'The username % is already taken', target_username USING ERRCODE = '42P10';
+		RETURN TRUE;
+	END IF;
+	RETURN FALSE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION is_email_taken(target_email VARCHAR) RETURNS BOOLEAN
+AS $$
+BEGIN
+	IF EXISTS(SELECT 1 from accounts WHERE lower(email) = lower(target_email)) = true THEN
+		RAISE EXCEPTION 'The email % is already taken', target_email USING ERRCODE = '42P11';
+		RETURN TRUE;
+	END IF;
+	RETURN FALSE;
+END;
+$$ LANGUAGE plpgsql;
+
+-- An account may change the case of its own username or email.
+CREATE OR REPLACE FUNCTION update_account(target_id VARCHAR, new_username VARCHAR, new_email VARCHAR)
+	RETURNS setof accounts
+AS $$
+BEGIN
+	IF new_username IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(username) = lower(new_username)) THEN
+		PERFORM is_username_taken(new_username);
+	END IF;
+	IF new_email IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(email) = lower(new_email)) THEN
+		PERFORM is_email_taken(new_email);
+	END IF;
+	UPDATE accounts SET
+		username = COALESCE(new_username, accounts.username),
+		email = COALESCE(new_email, accounts.email)
+	WHERE accounts.id = target_id;
+	IF NOT FOUND THEN
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
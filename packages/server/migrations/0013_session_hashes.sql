@@ -0,0 +1,5 @@
+-- Sessions are stored by the sha256 (hex) of their cookie value or
+-- access token, like access and refresh tokens. Hashes the ids of the
+-- sessions that already exist, their cookies keep working.
+
+UPDATE sessions SET session_id = encode(sha256(convert_to(session_id, 'UTF8')), 'hex');
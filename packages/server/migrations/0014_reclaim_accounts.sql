@@ -0,0 +1,21 @@
+-- An account whose email was never confirmed can be taken over by the
+-- first OpenID Connect login that proves the email. Whoever registered it
+-- before loses every way back in.
+
+CREATE OR REPLACE FUNCTION reclaim_account(target_id VARCHAR, new_password VARCHAR)
+	RETURNS BOOLEAN
+AS $$
+BEGIN
+	UPDATE accounts SET password = new_password WHERE accounts.id = target_id;
+	IF NOT FOUND THEN
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	DELETE FROM sessions WHERE sessions.account_id = target_id;
+	DELETE FROM access_tokens WHERE access_tokens.account_id = target_id;
+	DELETE FROM one_time_tokens WHERE one_time_tokens.account_id = target_id;
+	DELETE FROM two_factor WHERE two_factor.account_id = target_id;
+	DELETE FROM recovery_codes WHERE recovery_codes.account_id = target_id;
+	DELETE FROM external_identities WHERE external_identities.account_id = target_id;
+	RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,28 @@
+-- With email confirmation on, a changed email has to be confirmed again,
+-- the account drops back to the None rank in the same update. Changing
+-- only the case keeps the address.
+
+DROP FUNCTION IF EXISTS update_account(VARCHAR, VARCHAR, VARCHAR);
+
+CREATE OR REPLACE FUNCTION update_account(target_id VARCHAR, new_username VARCHAR, new_email VARCHAR, unconfirm BOOLEAN)
+	RETURNS setof accounts
+AS $$
+BEGIN
+	IF new_username IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(username) = lower(new_username)) THEN
+		PERFORM is_username_taken(new_username);
+	END IF;
+	IF new_email IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(email) = lower(new_email)) THEN
+		PERFORM is_email_taken(new_email);
+	END IF;
+	UPDATE accounts SET
+		username = COALESCE(new_username, accounts.username),
+		email = COALESCE(new_email, accounts.email),
+		rank = CASE WHEN unconfirm AND new_email IS NOT NULL AND lower(new_email) <> lower(accounts.email)
+			THEN 'None'::public."Rank" ELSE accounts.rank END
+	WHERE accounts.id = target_id;
+	IF NOT FOUND THEN
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,45 @@
+-- Remembers which address an account confirmed last. The email only counts
+-- as confirmed while it is still that address, changing it (other than its
+-- case) also drops the links mailed for the old one. Nothing before this
+-- was confirmed on record, those accounts confirm again to link providers.
+
+ALTER TABLE accounts ADD COLUMN verified_email VARCHAR(254);
+
+CREATE OR REPLACE FUNCTION confirm_email(target_id VARCHAR)
+	RETURNS setof accounts
+AS $$
+BEGIN
+	UPDATE accounts SET
+		verified_email = accounts.email,
+		rank = CASE WHEN accounts.rank = 'None' THEN 'Member'::public."Rank" ELSE accounts.rank END
+	WHERE accounts.id = target_id;
+	IF NOT FOUND THEN
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION update_account(target_id VARCHAR, new_username VARCHAR, new_email VARCHAR, unconfirm BOOLEAN)
+	RETURNS setof accounts
+AS $$
+BEGIN
+	IF new_username IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(username) = lower(new_username)) THEN
+		PERFORM is_username_taken(new_username);
+	END IF;
+	IF new_email IS NOT NULL AND NOT EXISTS(SELECT 1 FROM accounts WHERE id = target_id AND lower(email) = lower(new_email)) THEN
+		PERFORM is_email_taken(new_email);
+		DELETE FROM one_time_tokens WHERE one_time_tokens.account_id = target_id AND purpose = 'email_verification';
+	END IF;
+	UPDATE accounts SET
+		username = COALESCE(new_username, accounts.username),
+		email = COALESCE(new_email, accounts.email),
+		rank = CASE WHEN unconfirm AND new_email IS NOT NULL AND lower(new_email) <> lower(accounts.email)
+			THEN 'None'::public."Rank" ELSE accounts.rank END
+	WHERE accounts.id = target_id;
+	IF NOT FOUND THEN
+		RAISE EXCEPTION 'No account found with id %', target_id USING ERRCODE = '42P12';
+	END IF;
+	RETURN QUERY SELECT * FROM accounts WHERE accounts.id = target_id;
+END;
+$$ LANGUAGE plpgsql;
@@ -1,36 +1,33 @@
+use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
 
-#![feature(allocator_api)]
-use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}, borrow::Cow, result};
-use std::fs::File;
-use std::path::Path;
-
-use deadpool_postgres::Pool;
-use pbkdf2::{
-    password_hash::{
-        rand_core::OsRng,
-        PasswordHash, PasswordHasher, PasswordVerifier, SaltString, Salt
-    },
-    Pbkdf2
-};
+use nanoid::nanoid;
 use postgres_types::{ToSql, FromSql};
-use rocket::{serde::{Serialize, Deserialize, self}, route, http::Cookie, FromForm, request::{FromRequest, self}, Request};
-use tokio_postgres::{Row, Column, SimpleQueryMessage};
+use rocket::{serde::{Serialize, Deserialize}, FromForm};
+use tokio_postgres::Row;
 
 
-use crate::session::config::Session;
+use crate::{config::AppConfig, repository::Repositories, session::config::Session, validate::{self, ValidationErrors}};
 
-use super::{enums::{Rank, LoginMethod}, error::AccountError, routes};
+use super::{
+    enums::{Rank, LoginMethod},
+    error::AccountError,
+    hasher::{self, PasswordHasher},
+    lockout::{LoginAttempts, LoginLimiter},
+    oidc::{ExternalIdentity, IdTokenClaims},
+    onetime::{OneTimeToken, Purpose},
+    twofactor::{LoginOutcome, PendingLogin, TwoFactor}
+};
 
-/// Simple struct that helps select,insert,update and delete rows
-/// from the postgres database (for the account table :0).
+/// Simple struct that helps create, find, update and delete accounts,
+/// wherever the [`Repositories`] keep them.
 pub struct AccountConfig<'a> {
-    // The deadpool_pg pool instance (wrapped in an arc.).
-    pub pg_pool: &'a Pool
+    // The repositories managed by rocket.
+    pub repos: &'a Repositories
 }
 
 impl<'a> AccountConfig<'a> {
     /// Constructs a new [`AccountConfig`]. This method is used to help
-    /// instantiate the deadpool-postgres pool. A necessity.
+    /// hand the managed [`Repositories`] around. A necessity.
     /// for this class. 
     ///
     /// # Example
@@ -40,45 +37,38 @@ impl<'a> AccountConfig<'a> {
     /// ```rust
     /// use account::config::AccountConfig;
     ///
-    /// let acc_config = AccountConfig::new("table_name", dpg_pool);
+    /// let acc_config = AccountConfig::new(repos);
     /// ```
     #[inline(always)]
-    pub fn new(pg_pool: &'a Pool) -> Self {
-        let acc_config = AccountConfig {
-            pg_pool: &pg_pool
-        };
-        acc_config
+    pub fn new(repos: &'a Repositories) -> Self {
+        AccountConfig {
+            repos
+        }
     }
 
-    /// Creates a row inside the your table_name in Postgres
+    /// Stores a new account.
     ///
     /// # Example
     ///
-    /// Creates a row inside the table_name table.
+    /// Creates an account.
     ///
     /// ```rust
     /// use account::config::AccountConfig;
     ///
-    /// let acc_config = AccountConfig::new("table_name", dpg_pool);
-    /// let acc = Account::new("zeljko", "iloveyou", "zeljko@gmail.com");
+    /// let acc_config = AccountConfig::new(repos);
+    /// let acc = Account::new("zeljko", "iloveyou2", "zeljko@gmail.com", &repos.hasher).await?;
     /// acc_config.create(acc);
     /// ```
     pub async fn create(&self, acc: Account) -> Result<(), AccountError>{
-        let sql = "SELECT create_account($1, $2, $3, $4, $5)";
-        let result = self.quik_query(sql, &[&acc.id(), acc.username(), acc.email(), acc.password(), acc.rank()]).await;
-        match result {
-            Ok(_) => Ok({
-                println!("{}", "Success")
-            }),
-            Err(er) => {
-                let db_error = er.as_db_error();
-                let db_message = db_error.unwrap().message().to_string();
-                Err(AccountError::parse_db_error(db_error, &acc, db_message))
-            },
-        }
+        self.repos.accounts.create(&acc).await?;
+        println!("[Account] Created {}", acc.id());
+        Ok(())
     }
 
-    /// Authenticate with your preferred method.
+    /// Authenticate with your preferred method, the [`LoginLimiter`]
+    /// decides whether the password is even checked. Accounts with 2FA
+    /// get a [`PendingLogin`] instead of a session, see
+    /// [`AccountConfig::auth_two_factor`].
     ///
     /// # Example
     ///
@@ -87,40 +77,468 @@ impl<'a> AccountConfig<'a> {
     /// ```rust
     /// use account::config::AccountConfig;
     ///
-    /// let acc_config = AccountConfig::new("table_name",  dpg_pool);
+    /// let acc_config = AccountConfig::new(repos);
     /// 
     /// // login via username
-    /// acc_config.auth(LoginMethod::Username, "zeljko", "password")
+    /// acc_config.auth(LoginMethod::Username, "zeljko", "password", &limiter)
     /// 
     /// // login via email
-    /// acc_config.auth(LoginMethod::Email, "ilovz@gmail.com", "password")
+    /// acc_config.auth(LoginMethod::Email, "ilovz@gmail.com", "password", &limiter)
+    ///
+    /// // whichever the user typed
+    /// acc_config.auth(LoginMethod::detect(identifier), identifier, "password", &limiter)
     /// ```
     pub async fn auth(&self, //refractor...
         method: LoginMethod, 
         key: &str, 
-        pass: &str
-    ) -> Result<Session, AccountError> {
-        let sql = format!("SELECT * from accounts where {} ILIKE $1", method.to_string());
-        let response = self.quik_query(&sql, &[&key]).await;
-        match response {
-            Ok(res) => Ok({
-                if res.len() >= 1 {
-                    let acc = Account::from(&res[0]);
-                    let can_login = AccountConfig::quik_compare(&acc, &pass);
-                    if can_login {
-                        return Ok(Session::new(acc.id()))
-                    } else {
-                        return Err(AccountError::WrongPassword)
-                    }
-                }
-                return Err(AccountError::AccountNotFound(key.to_string()))
-            }),
-            Err(_) => todo!(), // never gets called ? even when length is 0 hmm
+        pass: &str,
+        limiter: &LoginLimiter<'_>
+    ) -> Result<LoginOutcome, AccountError> {
+        limiter.reserve_ip(self).await?;
+        let acc = match self.repos.accounts.find_login(&method, key).await? {
+            Some(acc) => acc,
+            // answered like a wrong password, in about the same time, so
+            // nobody can find out which usernames and emails exist.
+            None => {
+                hasher::verify_dummy_blocking(&self.repos.hasher, pass).await;
+                return Err(AccountError::WrongPassword);
+            },
+        };
+        self.compare_counted(&acc, pass, limiter).await?;
+        if acc.is_banned() {
+            return Err(AccountError::Banned)
+        }
+        self.rehash(&acc, pass).await;
+        self.login_outcome(&acc, limiter).await
+    }
+
+    /// Checks the password of an account that is logged in already, e.g.
+    /// before changing it. Counts like a login attempt, so a stolen
+    /// session can't be used to guess it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// acc_config.check_password(&authed.account, "iloveyou2", &limiter).await?;
+    /// ```
+    pub async fn check_password(&self, acc: &Account, pass: &str, limiter: &LoginLimiter<'_>) -> Result<(), AccountError> {
+        limiter.reserve_ip(self).await?;
+        self.compare_counted(acc, pass, limiter).await
+    }
+
+    /// Compares the password once the [`LoginLimiter`] counted the
+    /// attempt, a wrong one stays counted.
+    async fn compare_counted(&self, acc: &Account, pass: &str, limiter: &LoginLimiter<'_>) -> Result<(), AccountError> {
+        let failures = limiter.reserve_account(self, acc).await?;
+        if !self.quik_compare(acc, pass).await {
+            limiter.failed(self, acc, failures).await;
+            return Err(AccountError::WrongPassword);
+        }
+        limiter.passed(self, acc).await
+    }
+
+    /// Logs in with a mailed link instead of a password, see
+    /// `POST /api/account/login/link`. Ends up just like
+    /// [`AccountConfig::auth`], accounts with 2FA still need their code.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let token = acc_config.issue_token(&acc, Purpose::MagicLink).await?; // mailed
+    /// let outcome = acc_config.auth_link(&token, &limiter).await?;
+    /// ```
+    pub async fn auth_link(&self, token: &str, limiter: &LoginLimiter<'_>) -> Result<LoginOutcome, AccountError> {
+        let (one_time, acc) = self.find_token(token, Purpose::MagicLink).await?;
+        if acc.is_banned() {
+            return Err(AccountError::Banned)
+        }
+        self.consume_token(&one_time).await?;
+        self.login_outcome(&acc, limiter).await
+    }
+
+    /// Logs in an account found with [`AccountConfig::oidc_account`],
+    /// accounts with 2FA still need their code.
+    pub async fn auth_oidc(&self, acc: &Account, limiter: &LoginLimiter<'_>) -> Result<LoginOutcome, AccountError> {
+        if acc.is_banned() {
+            return Err(AccountError::Banned)
+        }
+        self.login_outcome(acc, limiter).await
+    }
+
+    /// Finds the account an OpenID Connect login belongs to. The first
+    /// login links the provider's subject to the account with the same
+    /// email, as long as both the provider and the account confirmed it,
+    /// or creates a new account (if registration is open). An account
+    /// that never confirmed any email is reclaimed instead. The bool tells
+    /// whether the account was just created.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let claims = oidc.exchange(&code, &flow).await?;
+    /// let (acc, created) = acc_config.oidc_account(&claims, app).await?;
+    /// let outcome = acc_config.auth_oidc(&acc, &limiter).await?;
+    /// ```
+    pub async fn oidc_account(&self, claims: &IdTokenClaims, app: &AppConfig) -> Result<(Account, bool), AccountError> {
+        if let Some(identity) = self.repos.identities.find(&claims.iss, &claims.sub).await? {
+            return Ok((self.find("id", &identity.account_id).await?, false));
+        }
+        let email = match claims.email.as_deref() {
+            Some(email) if validate::email(email).is_ok() => email,
+            _ => return Err(AccountError::OidcInvalid("the provider shared no valid email address".to_string())),
+        };
+        let (acc, created) = match self.repos.accounts.find_login(&LoginMethod::Email, email).await? {
+            // only an email confirmed on both sides proves it's the same person.
+            Some(acc) if claims.email_verified && acc.email_confirmed() => (acc, false),
+            // whoever registered the email never confirmed any, they lose the account.
+            Some(acc) if claims.email_verified && *acc.rank() == Rank::None && acc.verified_email.is_none() => (self.reclaim(acc).await?, false),
+            // the owner logs in with the password and confirms the email first.
+            Some(acc) => return Err(AccountError::EmailTaken(acc.email().to_string())),
+            None if !app.registration_open => return Err(AccountError::RegistrationClosed),
+            None => (self.create_external(claims, email, app).await?, true),
+        };
+        if !self.repos.identities.link(&ExternalIdentity::new(&claims.iss, &claims.sub, acc.id())).await? {
+            // linked by a login that finished at the same time.
+            let identity = self.repos.identities.find(&claims.iss, &claims.sub).await?
+                .ok_or_else(|| AccountError::AccountNotFound(claims.sub.clone()))?;
+            return Ok((self.find("id", &identity.account_id).await?, false));
+        }
+        println!("[Account] Linked {} to {}", acc.id(), claims.iss);
+        if claims.email_verified && !acc.email_confirmed() {
+            return Ok((self.confirm_email(&acc).await?, created));
+        }
+        Ok((acc, created))
+    }
+
+    /// Takes an account with an unconfirmed email away from whoever
+    /// registered it, the provider just proved the email belongs to
+    /// someone else. The password becomes random, every session, token,
+    /// 2FA and other linked identity is dropped.
+    async fn reclaim(&self, acc: Account) -> Result<Account, AccountError> {
+        self.repos.accounts.reclaim(&acc, &self.quik_hashpass(&nanoid!(48)).await).await?;
+        println!("[Account] Reclaimed {} with a verified email", acc.id());
+        Ok(acc)
+    }
+
+    /// Creates the account of a first OpenID Connect login. The username
+    /// comes from the provider (or the email) and gets a number when
+    /// taken, the password is random and can be set with a reset link.
+    async fn create_external(&self, claims: &IdTokenClaims, email: &str, app: &AppConfig) -> Result<Account, AccountError> {
+        let wanted = claims.preferred_username.as_deref()
+            .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+        let mut base: String = wanted.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .take(validate::USERNAME_MAX - 5)
+            .collect();
+        while base.len() < validate::USERNAME_MIN {
+            base.push('_');
+        }
+        let mut username = base.clone();
+        for _ in 0..5 {
+            let acc = Account {
+                username: username.clone(),
+                password: self.quik_hashpass(&nanoid!(48)).await,
+                email: email.to_string(),
+                rank: if claims.email_verified || !app.verify_email { Rank::Member } else { Rank::None },
+                ..Default::default()
+            };
+            match self.repos.accounts.create(&acc).await {
+                Ok(()) => return Ok(acc),
+                Err(AccountError::UsernameTaken(_)) => username = format!("{}_{}", base, nanoid!(4, &"0123456789".chars().collect::<Vec<_>>())),
+                Err(er) => return Err(er),
+            }
+        }
+        Err(AccountError::UsernameTaken(username))
+    }
+
+    /// Hands out a session once the password (or a mailed link) checked
+    /// out, or a [`PendingLogin`] if the account has 2FA.
+    async fn login_outcome(&self, acc: &Account, limiter: &LoginLimiter<'_>) -> Result<LoginOutcome, AccountError> {
+        // the failures are only forgotten once the code was right too,
+        // the password alone mustn't reset the guesses on the code.
+        if self.repos.two_factor.find(acc.id()).await?.is_some_and(|two_factor| two_factor.enabled) {
+            let pending_token = self.issue_token(acc, Purpose::TwoFactorLogin).await?;
+            return Ok(LoginOutcome::TwoFactor(PendingLogin {
+                pending_token,
+                expires_in: Purpose::TwoFactorLogin.lifetime() / 1000
+            }));
+        }
+        limiter.succeeded(self, acc).await?;
+        Ok(LoginOutcome::Session(Box::new(Session::new(acc.id()))))
+    }
+
+    /// The second step of a login with 2FA, takes the token
+    /// [`AccountConfig::auth`] handed out and a code from the
+    /// authenticator app or a recovery code. Wrong codes count as failed
+    /// logins.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let session = acc_config.auth_two_factor(&pending_token, "123456", &limiter).await?;
+    /// ```
+    pub async fn auth_two_factor(&self, pending_token: &str, code: &str, limiter: &LoginLimiter<'_>) -> Result<Session, AccountError> {
+        limiter.reserve_ip(self).await?;
+        let (one_time, acc) = self.find_token(pending_token, Purpose::TwoFactorLogin).await?;
+        // banned after the password step, no branch below may hand out a session.
+        if acc.is_banned() {
+            return Err(AccountError::Banned)
+        }
+        let failures = limiter.reserve_account(self, &acc).await?;
+        let two_factor = match self.repos.two_factor.find(acc.id()).await? {
+            Some(two_factor) if two_factor.enabled => two_factor,
+            // disabled in the meantime, the password was right.
+            _ => {
+                self.consume_token(&one_time).await?;
+                limiter.passed(self, &acc).await?;
+                limiter.succeeded(self, &acc).await?;
+                return Ok(Session::new(acc.id()));
+            },
+        };
+        if !self.check_code(&acc, &two_factor, code).await? {
+            limiter.failed(self, &acc, failures).await;
+            return Err(AccountError::WrongCode);
+        }
+        limiter.passed(self, &acc).await?;
+        self.consume_token(&one_time).await?;
+        limiter.succeeded(self, &acc).await?;
+        Ok(Session::new(acc.id()))
+    }
+
+    /// Checks a code from the authenticator app, or else uses up a
+    /// recovery code.
+    async fn check_code(&self, acc: &Account, two_factor: &TwoFactor, code: &str) -> Result<bool, AccountError> {
+        if let Some(step) = two_factor.verify(code) {
+            return self.repos.two_factor.use_step(acc.id(), step).await;
+        }
+        let used = self.repos.two_factor.use_recovery_code(acc.id(), &TwoFactor::hash_recovery_code(code)).await?;
+        if used {
+            println!("[Account] {} used a recovery code", acc.id());
+        }
+        Ok(used)
+    }
+
+    /// Creates a new TOTP secret for the account, it's only asked for on
+    /// login once [`AccountConfig::confirm_two_factor`] enabled it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let two_factor = acc_config.enroll_two_factor(&acc).await?;
+    /// println!("{}", two_factor.uri("blog", acc.username())); // otpauth://totp/blog:zeljko?secret=..
+    /// ```
+    pub async fn enroll_two_factor(&self, acc: &Account) -> Result<TwoFactor, AccountError> {
+        if self.repos.two_factor.find(acc.id()).await?.is_some_and(|two_factor| two_factor.enabled) {
+            return Err(AccountError::TwoFactorEnabled);
+        }
+        let two_factor = TwoFactor::new(acc.id());
+        self.repos.two_factor.save(&two_factor).await?;
+        Ok(two_factor)
+    }
+
+    /// Enables 2FA with a first code from the authenticator app and
+    /// returns the plain recovery codes.
+    pub async fn confirm_two_factor(&self, acc: &Account, code: &str) -> Result<Vec<String>, AccountError> {
+        let mut two_factor = match self.repos.two_factor.find(acc.id()).await? {
+            Some(two_factor) if two_factor.enabled => return Err(AccountError::TwoFactorEnabled),
+            Some(two_factor) => two_factor,
+            None => return Err(AccountError::TwoFactorDisabled),
+        };
+        two_factor.last_step = two_factor.verify(code).ok_or(AccountError::WrongCode)?;
+        two_factor.enabled = true;
+        self.repos.two_factor.save(&two_factor).await?;
+        let (codes, hashes) = TwoFactor::recovery_codes();
+        self.repos.two_factor.set_recovery_codes(acc.id(), &hashes).await?;
+        println!("[Account] {} enabled two-factor authentication", acc.id());
+        Ok(codes)
+    }
+
+    /// Turns 2FA off and forgets the secret and recovery codes.
+    pub async fn disable_two_factor(&self, acc: &Account) -> Result<(), AccountError> {
+        if !self.repos.two_factor.find(acc.id()).await?.is_some_and(|two_factor| two_factor.enabled) {
+            return Err(AccountError::TwoFactorDisabled);
+        }
+        self.repos.two_factor.delete(acc.id()).await?;
+        println!("[Account] {} disabled two-factor authentication", acc.id());
+        Ok(())
+    }
+
+    /// Forgets the failed logins of an account, so it can log in again
+    /// right away.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// acc_config.unlock(&acc).await?;
+    /// ```
+    pub async fn unlock(&self, acc: &Account) -> Result<(), AccountError> {
+        self.repos.attempts.clear(&LoginAttempts::account_key(acc.id())).await
+    }
+
+    /// Replaces a hash made with an older algorithm or older costs, the
+    /// plain password is only known right after a successful login.
+    /// Failing to do so never stops the login.
+    async fn rehash(&self, acc: &Account, pass: &str) {
+        if !self.repos.hasher.needs_rehash(acc.password()) {
+            return;
         }
+        match self.repos.accounts.set_password(acc, &self.quik_hashpass(pass).await).await {
+            Ok(_) => println!("[Account] Upgraded the password hash of {}", acc.id()),
+            Err(er) => println!("[Account] Could not upgrade the password hash of {}: {}", acc.id(), er),
+        }
+    }
+
+    /// Changes the username and/or email of an account, the same taken
+    /// checks as [`AccountConfig::create`] apply. With `verify_email` on
+    /// a changed email has to be confirmed again, the account drops back
+    /// to [`Rank::None`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let update = AccountUpdate { username: Some("zeljko2".to_string()), ..Default::default() };
+    /// let acc: Account = acc_config.update(&acc, &update, app).await?;
+    /// ```
+    pub async fn update(&self, acc: &Account, update: &AccountUpdate, app: &AppConfig) -> Result<Account, AccountError> {
+        self.repos.accounts.update(acc, update, app.verify_email).await
     }
 
-    /// Finds an Account by their `field` inside the database and returns
-    /// [`Account`].
+    /// Deletes an account together with its sessions. Threads are kept
+    /// but no longer point to their author.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// acc_config.delete(&acc).await?;
+    /// ```
+    pub async fn delete(&self, acc: &Account) -> Result<(), AccountError> {
+        self.repos.accounts.delete(acc).await?;
+        println!("[Account] {} was deleted", acc.id());
+        Ok(())
+    }
+
+    /// Changes the rank of an account.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let acc: Account = acc_config.set_rank(&acc, Rank::Moderator).await?;
+    /// ```
+    pub async fn set_rank(&self, acc: &Account, rank: Rank) -> Result<Account, AccountError> {
+        let changed = self.repos.accounts.set_rank(acc, rank.clone()).await?;
+        println!("[Account] {} is now {}", acc.id(), rank);
+        Ok(changed)
+    }
+
+    /// Records the current email as confirmed, see
+    /// [`Account::email_confirmed`]. A None account becomes a Member.
+    pub async fn confirm_email(&self, acc: &Account) -> Result<Account, AccountError> {
+        let changed = self.repos.accounts.confirm_email(acc).await?;
+        println!("[Account] {} confirmed its email", acc.id());
+        Ok(changed)
+    }
+
+    /// Bans or unbans an account, banning also ends all of its sessions.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let acc: Account = acc_config.set_banned(&acc, true).await?;
+    /// ```
+    pub async fn set_banned(&self, acc: &Account, banned: bool) -> Result<Account, AccountError> {
+        let changed = self.repos.accounts.set_banned(acc, banned).await?;
+        println!("[Account] {} banned: {}", acc.id(), banned);
+        Ok(changed)
+    }
+
+    /// Replaces the password of an account, the new one has to be
+    /// validated already.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// acc_config.change_password(&acc, "iloveyou3").await?;
+    /// ```
+    pub async fn change_password(&self, acc: &Account, pass: &str) -> Result<(), AccountError> {
+        self.repos.accounts.set_password(acc, &self.quik_hashpass(pass).await).await?;
+        println!("[Account] Changed the password of {}", acc.id());
+        Ok(())
+    }
+
+    /// Creates a [`OneTimeToken`] for the account and returns the plain
+    /// token to mail. Older tokens with the same purpose stop working.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use account::config::AccountConfig;
+    ///
+    /// let token = acc_config.issue_token(&acc, Purpose::PasswordReset).await?;
+    /// ```
+    pub async fn issue_token(&self, acc: &Account, purpose: Purpose) -> Result<String, AccountError> {
+        let (one_time, token) = OneTimeToken::new(acc.id(), purpose);
+        self.repos.one_time.remove_all(acc.id(), &one_time.purpose).await?;
+        self.repos.one_time.create(&one_time).await?;
+        println!("[Account] Issued a {} token for {}", purpose, acc.id());
+        Ok(token)
+    }
+
+    /// Fails with [`AccountError::TooManyRequests`] while the last token
+    /// with this purpose is younger than [`OneTimeToken::RESEND_INTERVAL`].
+    pub async fn throttle_token(&self, acc: &Account, purpose: Purpose) -> Result<(), AccountError> {
+        let latest = self.repos.one_time.latest(acc.id(), &purpose.to_string()).await?;
+        match latest.and_then(|one_time| one_time.resend_after()) {
+            Some(retry_after) => Err(AccountError::TooManyRequests(retry_after)),
+            None => Ok(()),
+        }
+    }
+
+    /// Finds the account a mailed token belongs to without redeeming it,
+    /// unknown and expired tokens fail with [`AccountError::InvalidToken`].
+    pub async fn find_token(&self, token: &str, purpose: Purpose) -> Result<(OneTimeToken, Account), AccountError> {
+        let one_time = match self.repos.one_time.find(&Session::hash(token), &purpose.to_string()).await? {
+            Some(one_time) if !one_time.is_expired() => one_time,
+            _ => return Err(AccountError::InvalidToken),
+        };
+        match self.find("id", &one_time.account_id).await {
+            Ok(acc) => Ok((one_time, acc)),
+            Err(AccountError::AccountNotFound(_)) => Err(AccountError::InvalidToken),
+            Err(er) => Err(er),
+        }
+    }
+
+    /// Redeems a token found with [`AccountConfig::find_token`], only
+    /// the first of two racing requests gets through.
+    pub async fn consume_token(&self, one_time: &OneTimeToken) -> Result<(), AccountError> {
+        match self.repos.one_time.consume(&one_time.token_hash).await? {
+            Some(_) => Ok(()),
+            None => Err(AccountError::InvalidToken),
+        }
+    }
+
+    /// Finds an Account by their `field` and returns [`Account`].
     ///
     /// Available Fields: **id**, **username** and **email**.
     /// 
@@ -131,24 +549,20 @@ impl<'a> AccountConfig<'a> {
     /// ```rust
     /// use account::config::AccountConfig;
     ///
-    /// let acc_config = AccountConfig::new(dpg_pool); 
+    /// let acc_config = AccountConfig::new(repos); 
     /// 
     /// let acc_by_id: Account = acc_config.find('id', '1673919920888240800');
     /// let acc_by_username: Account = acc_config.find('username', 'zeljko');
     /// let acc_by_email: Account = acc_config.find('email', "zeljko@gmail.com");
-    /// let acc_by_session Account = acc_config.find('session', 'dDSwUKaRICtMOkQDRTB54');
     /// ``
     pub async fn find(&self, find: &str, value: &str) -> Result<Account, AccountError> {
-        let sql = format!("select * from find_by_{}($1)", find);
-        let response = self.quik_query(&sql, &[&value]).await;
-        match response {
-            Ok(res) => Ok({
-                Account::from(&res[0])
-            }),
-            Err(er) => {
-                Err(AccountError::AccountNotFound(value.to_string()))
-            },
-        }
+        self.repos.accounts.find(find, value).await
+    }
+
+    /// Finds the account an email belongs to whatever its case, like
+    /// logging in with the email does.
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<Account>, AccountError> {
+        self.repos.accounts.find_login(&LoginMethod::Email, email).await
     }
 
     //
@@ -156,7 +570,8 @@ impl<'a> AccountConfig<'a> {
     //  (shorthands)
     // 
 
-    /// A shorthand for generating a hashed password from the Pbkdf2 library.
+    /// A shorthand for hashing a password with the configured
+    /// [`PasswordHasher`], off the async workers.
     ///
     /// # Example
     /// 
@@ -165,17 +580,16 @@ impl<'a> AccountConfig<'a> {
     /// ```rust
     /// use account::config::AccountConfig;
     /// 
-    /// let acc = Account::new("zeljko", "iloveyou", "zeljko@gmail.com");
-    /// let salt = AccountConfig::quick_pass(&acc);
-    /// println!("{}", salt); // UtCDtWw96w324K8NIW/YANc+aHvaCMvc9yeqiyDDDTw
+    /// let hash = acc_config.quik_hashpass("iloveyou2").await;
+    /// println!("{}", hash); // $argon2id$v=19$m=19456,t=2,p=1$..
     /// ```
-    
-    fn quik_hashpass(pass: &str) -> String {
-        Pbkdf2.hash_password(
-            pass.as_bytes(), &AccountConfig::quik_salt()).unwrap().to_string()
+    pub async fn quik_hashpass(&self, pass: &str) -> String {
+        hasher::hash_blocking(&self.repos.hasher, pass).await
     }
 
-    /// A shorthand for comparing two hashes from the Pbkdf2 library.
+    /// A shorthand for comparing a password with the stored hash, any
+    /// hash the [`PasswordHasher`] knows works. Runs off the async
+    /// workers like [`AccountConfig::quik_hashpass`].
     ///
     /// # Example
     /// 
@@ -184,34 +598,16 @@ impl<'a> AccountConfig<'a> {
     /// ```rust
     /// use account::config::AccountConfig;
     /// 
-    /// let acc = Account::new("zeljko", "iloveyou", "zeljko@gmail.com");
+    /// let acc = Account::new("zeljko", "iloveyou2", "zeljko@gmail.com", &repos.hasher).await?;
     /// 
     /// // false
-    /// let pass_comp_1 = AccountConfig::quik_compare(&acc, "idontloveyou");
+    /// let pass_comp_1 = acc_config.quik_compare(&acc, "idontloveyou").await;
     /// 
     /// // true
-    /// let pass_comp_2 = AccountConfig::quik_compare(&acc, "iloveyou");
-    /// ```
-    fn quik_compare(acc: &Account, pass: &str) -> bool {
-        let stored_pass = PasswordHash::new(acc.password()).unwrap();
-        Pbkdf2.verify_password(pass.as_bytes(), &stored_pass).is_ok()
-    }
-    
-    /// A shorthand for generating a salt from the pbkdf2 library.
-    ///
-    /// # Example
-    /// 
-    /// Create a salt.
-    ///
-    /// ```rust
-    /// use account::config::AccountConfig;
-    ///
-    /// let salt = AccountConfig::quick_salt();
-    /// println!("{}", salt); // AJJfAf2HCkUsVk4UaOg8uA
+    /// let pass_comp_2 = acc_config.quik_compare(&acc, "iloveyou2").await;
     /// ```
-    #[inline(always)]
-    fn quik_salt() -> SaltString {
-        SaltString::generate(&mut OsRng)
+    pub async fn quik_compare(&self, acc: &Account, pass: &str) -> bool {
+        hasher::verify_blocking(&self.repos.hasher, pass, acc.password()).await
     }
 
     /// A shorthand for generating a user's id.
@@ -231,32 +627,6 @@ impl<'a> AccountConfig<'a> {
         let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         time.as_nanos().to_string()
     }
-
-    /// A shorthand for executing queries this function
-    /// should not be used outside this module.
-    ///
-    /// # Example
-    ///
-    /// Execute a postgres query
-    /// 
-    /// ```rust
-    /// use account::config::AccountConfig;
-    ///
-    /// &self.quik_query("SELECT * from table_name");
-    /// ```
-    pub async fn quik_query(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, tokio_postgres::Error>
-    {
-        let pg = &self.pg_pool.get().await.unwrap();
-        let stmt = pg.prepare(&sql).await.unwrap();
-        pg.query(&stmt, params).await
-    }
-
-    pub async fn quik_simple_query(&self, sql: &str) ->
-    Result<Vec<Row>, tokio_postgres::Error>
-    {
-        let pg = &self.pg_pool.get().await.unwrap();
-        pg.query(sql, &[]).await
-    }
 }
 
 /// The blueprint for an account. 
@@ -264,34 +634,170 @@ impl<'a> AccountConfig<'a> {
 /// You can add and delete to your liking but make sure your table
 /// reflects your changes.
 /// 
-/// Look at sql/create_table_accounts.sql for more information on
-/// how to create a table.
+/// The columns are read by index, look at migrations/0001_schema.sql
+/// and add a new migration when you change them.
 /// 
 /// All the 'meaty' logic should be handled in AccountConfig not here.
 #[derive(Debug, Clone, ToSql, FromSql, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct Account {
     #[serde(default)]
+    pub(crate) id: String,
+    pub(crate) username: String,
+    // only ever read from requests, the hash never leaves the server.
+    #[serde(skip_serializing)]
+    pub(crate) password: String,
+    pub(crate) email: String,
+    #[serde(default)]
+    pub(crate) rank: Rank,
+    #[serde(default)]
+    pub(crate) banned: bool,
+    // the address the account confirmed last, never read from requests.
+    #[serde(skip)]
+    pub(crate) verified_email: Option<String>
+}
+
+/// What everyone can see of an account, see `GET /api/account/{username}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountProfile {
     id: String,
     username: String,
-    password: String,
-    email: String,
-    #[serde(default)]
     rank: Rank
 }
 
-// you can easily add username support.. due to the AccountConfig#auth() method.
-#[derive(FromForm)]
-pub struct AccountLogin {
+impl From<Account> for AccountProfile {
+    fn from(acc: Account) -> Self {
+        AccountProfile {
+            id: acc.id,
+            username: acc.username,
+            rank: acc.rank
+        }
+    }
+}
+
+/// The body of `PATCH /api/account/me`, fields left out are
+/// not changed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountUpdate {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    /// Only needed to change the email.
+    pub current_password: Option<String>,
+}
+
+impl AccountUpdate {
+    /// Whether the email of `acc` would change, a different case is
+    /// still the same address.
+    pub fn changes_email(&self, acc: &Account) -> bool {
+        self.email.as_deref().is_some_and(|email| email.to_lowercase() != acc.email().to_lowercase())
+    }
+
+    /// Checks the fields that are being changed.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(username) = &self.username {
+            errors.check(validate::username(username));
+        }
+        if let Some(email) = &self.email {
+            errors.check(validate::email(email));
+        }
+        errors.finish()
+    }
+}
+
+/// The body of `POST /api/account/verify`, `token` comes from the
+/// mailed link.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountVerify {
+    pub token: String,
+}
+
+/// The body of `POST /api/account/unlock`, `token` comes from the
+/// mailed link.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountUnlock {
+    pub token: String,
+}
+
+/// The body of `POST /api/account/password`.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// The body of `POST /api/account/password/forgot`.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordForgot {
     pub email: String,
+}
+
+/// The body of `POST /api/account/password/reset`, `token` comes from
+/// the mailed link.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordReset {
+    pub token: String,
+    pub password: String,
+}
+
+/// The body of `POST /api/account/login` (a form) and
+/// `POST /api/session/token` (json). `identifier` is a username or an
+/// email, `email` is still accepted for older clients.
+#[derive(FromForm, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountLogin {
+    #[field(name = "identifier")]
+    #[field(name = "email")]
+    #[serde(alias = "email")]
+    pub identifier: String,
     pub password: String,
 }
 
+impl AccountLogin {
+    pub fn method(&self) -> LoginMethod {
+        LoginMethod::detect(&self.identifier)
+    }
+}
+
+/// The body of `POST /api/account/login/link`.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+/// The body of `POST /api/account/login/link/redeem` (a form), `token`
+/// comes from the mailed link.
+#[derive(FromForm)]
+pub struct MagicLinkLogin {
+    pub token: String,
+}
+
+/// The second step of a login with 2FA, `POST /api/account/login/two-factor`
+/// (a form) and `POST /api/session/two-factor` (json).
+#[derive(FromForm, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorLogin {
+    pub pending_token: String,
+    pub code: String,
+}
+
 impl Account {
     /// Constructs a new [`Account`]. This method provides
     /// the data for the [`AccountConfig`] in order for it
     /// execute a variety of functions.
     ///
+    /// The input is checked first (see [`crate::validate`]), the
+    /// password is only hashed (with `hasher`, on tokio's blocking
+    /// threads) once it passed.
+    ///
     /// # Example
     ///
     /// Create an Account.
@@ -299,17 +805,24 @@ impl Account {
     /// ```rust
     /// use account::config::AccountConfig;
     ///
-    /// let acc = Account::new("zeljko", "iloveyou", "zeljko@gmail.com");
+    /// let acc = Account::new("zeljko", "iloveyou2", "zeljko@gmail.com", &repos.hasher).await?;
     /// 
     /// println!("{}", acc.username()) // zeljko
     /// ```
-    #[inline(always)]
-    pub fn new(username: &str, password: &str, email: &str) -> Self {
-        let mut acc = Account::default();
-        acc.username = username.to_string();
-        acc.password = AccountConfig::quik_hashpass(password);
-        acc.email = email.to_string();
-        acc
+    pub async fn new(username: &str, password: &str, email: &str, hasher: &Arc<dyn PasswordHasher>) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors
+            .check(validate::username(username))
+            .check(validate::email(email))
+            .check(validate::password(password, username));
+        errors.finish()?;
+
+        Ok(Account {
+            username: username.to_string(),
+            password: hasher::hash_blocking(hasher, password).await,
+            email: email.to_string(),
+            ..Default::default()
+        })
     }
 
     // Returns the id of Account
@@ -336,6 +849,16 @@ impl Account {
     pub fn rank(&self) -> &Rank {
         &self.rank
     }
+
+    // Returns whether the Account is banned
+    pub fn is_banned(&self) -> bool {
+        self.banned
+    }
+
+    // Returns whether the current email is the one the Account confirmed
+    pub fn email_confirmed(&self) -> bool {
+        self.verified_email.as_ref().is_some_and(|verified| verified.to_lowercase() == self.email.to_lowercase())
+    }
 }
 
 /// The default configuration for the Account struct.
@@ -345,8 +868,8 @@ impl Account {
 /// you update your Postgres 'Types' in the the data-
 /// base.
 /// 
-/// Look at sql/create_types.sql how to create new
-/// types in postgres.
+/// Look at migrations/0001_schema.sql how the types are
+/// created in postgres.
 /// 
 /// Anything with an ! should be edited with caution.
 impl Default for Account {
@@ -356,7 +879,9 @@ impl Default for Account {
             username: Default::default(), 
             password: Default::default(), // !
             email: Default::default(), 
-            rank: Rank::default()  // !
+            rank: Rank::default(),  // !
+            banned: false,
+            verified_email: None
         }
     }
 }
@@ -368,7 +893,9 @@ impl From<&Row> for Account {
             username: value.get(1),
             email: value.get(2),  
             password: value.get(3), 
-            rank: value.get(4)
+            rank: value.get(4),
+            banned: value.get(5),
+            verified_email: value.get(7)
         }
     }
 }
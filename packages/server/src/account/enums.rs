@@ -3,14 +3,16 @@ use core::fmt;
 use postgres_types::{ToSql, FromSql};
 use rocket::serde::{Serialize, Deserialize};
 
+/// Ranks are ordered from lowest to highest, so `rank >= Rank::Moderator`
+/// works as expected.
 #[derive(
-    Default, Debug, Clone, PartialEq, 
+    Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord,
     Serialize, Deserialize, 
     ToSql, FromSql
 )]
 #[serde(crate = "rocket::serde")]
 pub enum Rank {
-    None, // if you want to add sms or smtp verification this will be of use to you.
+    None, // hasn't confirmed their email yet, see `verify_email` in config::AppConfig.
     #[default]
     Member,
     Moderator,
@@ -18,6 +20,33 @@ pub enum Rank {
     Owner
 }
 
+impl Rank {
+    /// Whether this rank holds the given [`Permission`].
+    pub fn can(&self, permission: &Permission) -> bool {
+        *self >= permission.min_rank()
+    }
+
+    /// The rank one step up, Owner can only be handed out by hand.
+    pub fn promoted(&self) -> Option<Rank> {
+        match self {
+            Rank::None => Some(Rank::Member),
+            Rank::Member => Some(Rank::Moderator),
+            Rank::Moderator => Some(Rank::Admin),
+            Rank::Admin | Rank::Owner => None,
+        }
+    }
+
+    /// The rank one step down, accounts are never demoted below Member.
+    pub fn demoted(&self) -> Option<Rank> {
+        match self {
+            Rank::Moderator => Some(Rank::Member),
+            Rank::Admin => Some(Rank::Moderator),
+            Rank::Owner => Some(Rank::Admin),
+            Rank::None | Rank::Member => None,
+        }
+    }
+}
+
 impl fmt::Display for Rank {
     fn fmt(
         &self, 
@@ -28,12 +57,60 @@ impl fmt::Display for Rank {
 } 
 
 
+/// Everything an account may or may not do. Which rank is needed for
+/// what lives in [`Permission::min_rank`], change it there and every
+/// route follows.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Permission {
+    CreateThread,
+    EditOwnThread,
+    EditOthersThread,
+    PurgeThread,
+    BanUsers,
+    ChangeRanks
+}
+
+impl Permission {
+    /// The permission table, the lowest rank holding each permission.
+    pub fn min_rank(&self) -> Rank {
+        match self {
+            Permission::CreateThread => Rank::Member,
+            Permission::EditOwnThread => Rank::Member,
+            Permission::EditOthersThread => Rank::Moderator,
+            Permission::PurgeThread => Rank::Admin,
+            Permission::BanUsers => Rank::Admin,
+            Permission::ChangeRanks => Rank::Owner,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(
+        &self, 
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+
 #[derive(PartialEq, Debug)]
 pub enum LoginMethod {
     Username,
     Email
 }
 
+impl LoginMethod {
+    /// Usernames can't contain an `@`, so anything with one is an email.
+    pub fn detect(identifier: &str) -> LoginMethod {
+        if identifier.contains('@') {
+            LoginMethod::Email
+        } else {
+            LoginMethod::Username
+        }
+    }
+}
+
 impl fmt::Display for LoginMethod {
     fn fmt(
         &self, 
@@ -1,8 +1,9 @@
 use std::fmt;
 
-use tokio_postgres::error::DbError;
+use rocket::http::Status;
+use crate::db::QueryError;
 
-use super::config::Account;
+use super::{config::Account, enums::Permission};
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum AccountError {
@@ -10,13 +11,41 @@ pub enum AccountError {
     EmailTaken(String),
     InvalidFormat(String),
     AccountNotFound(String),
-    WrongPassword
+    WrongPassword,
+    Unauthenticated,
+    SessionExpired,
+    MissingPermission(Permission),
+    MissingScope(String),
+    SessionRequired,
+    NotSubordinate(String),
+    RegistrationClosed,
+    Banned,
+    InvalidToken,
+    Unverified,
+    AlreadyVerified,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    WrongCode,
+    /// Seconds until the next try is allowed.
+    TooManyRequests(u64),
+    /// A state changing request came without a valid csrf token, see
+    /// [`crate::session::csrf`].
+    InvalidCsrfToken,
+    /// `[app.oidc]` isn't enabled.
+    OidcDisabled,
+    /// The provider's answer didn't check out, or the login was started
+    /// elsewhere.
+    OidcInvalid(String),
+    /// The provider could not be reached or answered with an error.
+    OidcUnavailable(String),
+    Database(String),
+    Unavailable(String)
 }
 
 
 impl fmt::Display for AccountError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
+        match self {
             AccountError::UsernameTaken(username) => write!(
                 f,
                 "The username {} is taken.",
@@ -41,25 +70,157 @@ impl fmt::Display for AccountError {
                 f,
                 "The password you entered is incorrect.",
             ),
+            AccountError::Unauthenticated => write!(
+                f,
+                "You need to be logged in.",
+            ),
+            AccountError::SessionExpired => write!(
+                f,
+                "Your session has expired, please log in again.",
+            ),
+            AccountError::MissingPermission(permission) => write!(
+                f,
+                "Your rank does not allow you to do that ({}).",
+                permission
+            ),
+            AccountError::MissingScope(scope) => write!(
+                f,
+                "This access token is missing the '{}' scope.",
+                scope
+            ),
+            AccountError::SessionRequired => write!(
+                f,
+                "Access tokens can't be used for this, please log in.",
+            ),
+            AccountError::NotSubordinate(username) => write!(
+                f,
+                "{} does not rank below you.",
+                username
+            ),
+            AccountError::RegistrationClosed => write!(
+                f,
+                "Registration is closed.",
+            ),
+            AccountError::Banned => write!(
+                f,
+                "This account has been banned.",
+            ),
+            AccountError::InvalidToken => write!(
+                f,
+                "This link is invalid or has expired.",
+            ),
+            AccountError::Unverified => write!(
+                f,
+                "Please confirm your email address first.",
+            ),
+            AccountError::AlreadyVerified => write!(
+                f,
+                "Your email address is already confirmed.",
+            ),
+            AccountError::TwoFactorEnabled => write!(
+                f,
+                "Two-factor authentication is already enabled.",
+            ),
+            AccountError::TwoFactorDisabled => write!(
+                f,
+                "Two-factor authentication is not enabled.",
+            ),
+            AccountError::WrongCode => write!(
+                f,
+                "The code you entered is incorrect.",
+            ),
+            AccountError::TooManyRequests(retry_after) => write!(
+                f,
+                "Too many attempts, please try again in {} seconds.",
+                retry_after
+            ),
+            AccountError::InvalidCsrfToken => write!(
+                f,
+                "The request is missing a valid csrf token, please reload the page and try again.",
+            ),
+            AccountError::OidcDisabled => write!(
+                f,
+                "Signing in with an external provider is not enabled.",
+            ),
+            AccountError::OidcInvalid(reason) => write!(
+                f,
+                "Could not sign you in with the provider: {}.",
+                reason
+            ),
+            AccountError::OidcUnavailable(_) => write!(
+                f,
+                "The login provider could not be reached, please try again later.",
+            ),
+            AccountError::Database(message) => write!(
+                f,
+                "Database error: {}",
+                message
+            ),
+            AccountError::Unavailable(message) => write!(
+                f,
+                "{}",
+                message
+            ),
         }
     }
 }
 
 impl AccountError {
-    pub fn parse_db_error(er: Option<&DbError>, acc: &Account, message: String) -> AccountError {
-        let error = er.unwrap();
-        let code = error.code().code().to_string();
-
-        // find a better way.
-        if code.eq("42P10") {
-            return AccountError::UsernameTaken(acc.username().to_string())
+    /// The http status a route should answer with for this error.
+    pub fn status(&self) -> Status {
+        match self {
+            AccountError::UsernameTaken(_)
+            | AccountError::EmailTaken(_)
+            | AccountError::AlreadyVerified
+            | AccountError::TwoFactorEnabled
+            | AccountError::TwoFactorDisabled => Status::Conflict,
+            AccountError::InvalidFormat(_) | AccountError::InvalidToken | AccountError::OidcInvalid(_) => Status::BadRequest,
+            AccountError::AccountNotFound(_) | AccountError::OidcDisabled => Status::NotFound,
+            AccountError::WrongPassword
+            | AccountError::WrongCode
+            | AccountError::Unauthenticated
+            | AccountError::SessionExpired => Status::Unauthorized,
+            AccountError::MissingPermission(_)
+            | AccountError::MissingScope(_)
+            | AccountError::SessionRequired
+            | AccountError::NotSubordinate(_)
+            | AccountError::RegistrationClosed
+            | AccountError::Banned
+            | AccountError::Unverified
+            | AccountError::InvalidCsrfToken => Status::Forbidden,
+            AccountError::TooManyRequests(_) => Status::TooManyRequests,
+            AccountError::OidcUnavailable(_) => Status::BadGateway,
+            AccountError::Database(_) => Status::InternalServerError,
+            AccountError::Unavailable(_) => Status::ServiceUnavailable,
         }
-        if code.eq("42P11") {
-            return AccountError::EmailTaken(acc.email().to_string())
-        }
-        if code.eq("42P12") {
-            return AccountError::AccountNotFound(acc.id().to_string())
+    }
+
+    /// Turns a failed query into an [`AccountError`], `acc` fills in
+    /// the taken/not found messages.
+    pub fn parse_db_error(er: &QueryError, acc: &Account) -> AccountError {
+        let error = match er {
+            QueryError::Unavailable(message) => return AccountError::Unavailable(message.clone()),
+            QueryError::Postgres(_) => match er.as_db_error() {
+                Some(error) => error,
+                None => return AccountError::Database(er.to_string())
+            },
+        };
+        match error.code().code() {
+            "42P10" => AccountError::UsernameTaken(acc.username().to_string()),
+            "42P11" => AccountError::EmailTaken(acc.email().to_string()),
+            "42P12" | "42P13" | "42P14" => AccountError::AccountNotFound(acc.id().to_string()),
+            // two signups racing past the taken checks.
+            "23505" if matches!(error.constraint(), Some("accounts_username_key" | "accounts_username_lower_key")) => {
+                AccountError::UsernameTaken(acc.username().to_string())
+            },
+            "23505" if matches!(error.constraint(), Some("accounts_email_key" | "accounts_email_lower_key")) => {
+                AccountError::EmailTaken(acc.email().to_string())
+            },
+            // data exceptions and constraint violations, e.g. the username domain.
+            code if code.starts_with("22") || code.starts_with("23") => {
+                AccountError::InvalidFormat(error.message().to_string())
+            },
+            _ => AccountError::Database(error.message().to_string()),
         }
-        return AccountError::InvalidFormat(message)
     }
 }
\ No newline at end of file
@@ -0,0 +1,228 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use rocket::{outcome::try_outcome, request::{FromRequest, Outcome}, Request};
+
+use crate::{
+    error::ApiError,
+    repository::Repositories,
+    session::{config::Session, cookie::SESSION_COOKIE, error::SessionError},
+    token::{config::{AccessToken, Scope}, error::TokenError}
+};
+
+use super::{config::{Account, AccountConfig}, enums::{Permission, Rank}, error::AccountError};
+
+/// A request guard for routes that need a logged in account.
+///
+/// Resolves an `Authorization: Bearer <token>` header or else the
+/// private `sid` cookie to its [`Session`] (or [`AccessToken`]) and
+/// [`Account`], anonymous or expired sessions are answered with a 401
+/// and banned accounts with a 403.
+///
+/// # Example
+///
+/// ```rust
+/// use account::guard::AuthedAccount;
+///
+/// #[get("/whoami")]
+/// pub async fn whoami(authed: AuthedAccount) -> String {
+///     authed.account.username().to_string()
+/// }
+/// ```
+pub struct AuthedAccount {
+    pub account: Account,
+    pub credential: Credential
+}
+
+/// How a request authenticated itself.
+pub enum Credential {
+    /// A login session, through the cookie or a bearer access token.
+    Session(Session),
+    /// A personal access token, limited to its scopes.
+    Token(AccessToken)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthedAccount {
+    type Error = AccountError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let bearer = req.headers().get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let sid = match bearer.or_else(|| req.cookies().get_private(SESSION_COOKIE).map(|sid| sid.value().to_string())) {
+            Some(sid) => sid,
+            None => return fail(req, AccountError::Unauthenticated),
+        };
+        let repos = match req.rocket().state::<Repositories>() {
+            Some(repos) => repos,
+            None => return fail(req, AccountError::Database("no repositories are managed".to_string())),
+        };
+        let cfg = AccountConfig::new(repos);
+
+        if sid.starts_with(AccessToken::PREFIX) {
+            return AuthedAccount::from_token(req, &cfg, &sid).await;
+        }
+
+        let mut session = match Session::find(&cfg, &sid).await {
+            Ok(session) => session,
+            Err(SessionError::SessionNotFound(_)) => return fail(req, AccountError::Unauthenticated),
+            Err(SessionError::Database(message)) => return fail(req, AccountError::Database(message)),
+            Err(SessionError::Unavailable(message)) => return fail(req, AccountError::Unavailable(message)),
+        };
+        if session.is_expired() {
+            // bearer sessions can still be refreshed.
+            if session.refresh_hash.is_none() {
+                let _ = session.revoke(&cfg).await;
+            }
+            return fail(req, AccountError::SessionExpired);
+        }
+
+        match cfg.find("id", &session.account_id).await {
+            Ok(account) if account.is_banned() => fail(req, AccountError::Banned),
+            Ok(account) => {
+                session.touch(&cfg).await;
+                Outcome::Success(AuthedAccount { account, credential: Credential::Session(session) })
+            },
+            Err(AccountError::AccountNotFound(_)) => fail(req, AccountError::Unauthenticated),
+            Err(er) => fail(req, er),
+        }
+    }
+}
+
+impl AuthedAccount {
+    async fn from_token(req: &Request<'_>, cfg: &AccountConfig<'_>, token: &str) -> Outcome<Self, AccountError> {
+        let mut acc_token = match AccessToken::find(cfg, token).await {
+            Ok(acc_token) => acc_token,
+            Err(TokenError::Database(message)) => return fail(req, AccountError::Database(message)),
+            Err(TokenError::Unavailable(message)) => return fail(req, AccountError::Unavailable(message)),
+            Err(_) => return fail(req, AccountError::Unauthenticated),
+        };
+        match cfg.find("id", &acc_token.account_id).await {
+            Ok(account) if account.is_banned() => fail(req, AccountError::Banned),
+            Ok(account) => {
+                acc_token.touch(cfg).await;
+                Outcome::Success(AuthedAccount { account, credential: Credential::Token(acc_token) })
+            },
+            Err(AccountError::AccountNotFound(_)) => fail(req, AccountError::Unauthenticated),
+            Err(er) => fail(req, er),
+        }
+    }
+
+    /// The login session behind the request, personal access tokens
+    /// have none and are refused.
+    pub fn session(&self) -> Result<&Session, AccountError> {
+        match &self.credential {
+            Credential::Session(session) => Ok(session),
+            Credential::Token(_) => Err(AccountError::SessionRequired),
+        }
+    }
+
+    /// Checks whether the credential may be used for `scope`, sessions
+    /// hold every scope.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// authed.require_scope(Scope::ThreadWrite)?;
+    /// ```
+    pub fn require_scope(&self, scope: Scope) -> Result<(), AccountError> {
+        match &self.credential {
+            Credential::Session(_) => Ok(()),
+            Credential::Token(acc_token) if acc_token.has_scope(scope) => Ok(()),
+            Credential::Token(_) => Err(AccountError::MissingScope(scope.to_string())),
+        }
+    }
+
+    /// Checks the account's rank against the permission table, accounts
+    /// that haven't confirmed their email get [`AccountError::Unverified`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// authed.require(Permission::CreateThread)?;
+    /// ```
+    pub fn require(&self, permission: Permission) -> Result<(), AccountError> {
+        if self.account.rank().can(&permission) {
+            Ok(())
+        } else if *self.account.rank() == Rank::None {
+            Err(AccountError::Unverified)
+        } else {
+            Err(AccountError::MissingPermission(permission))
+        }
+    }
+}
+
+/// The minimum rank a [`RequireRank`] guard lets through. Each marker
+/// points at an entry of the permission table instead of a hardcoded
+/// rank so [`Permission::min_rank`] stays the only place to change.
+pub trait MinRank: Send + Sync + 'static {
+    const PERMISSION: Permission;
+}
+
+/// Marker types for [`RequireRank`], e.g. `RequireRank<rank::Owner>`.
+pub mod rank {
+    use super::{MinRank, Permission};
+
+    /// Whoever may edit others' threads (Moderator by default).
+    pub struct Moderator;
+    /// Whoever may ban users (Admin by default).
+    pub struct Admin;
+    /// Whoever may change ranks (Owner by default).
+    pub struct Owner;
+
+    impl MinRank for Moderator {
+        const PERMISSION: Permission = Permission::EditOthersThread;
+    }
+
+    impl MinRank for Admin {
+        const PERMISSION: Permission = Permission::BanUsers;
+    }
+
+    impl MinRank for Owner {
+        const PERMISSION: Permission = Permission::ChangeRanks;
+    }
+}
+
+/// Like [`AuthedAccount`] but only lets accounts of rank `R` or higher
+/// through, everyone else gets a 403.
+///
+/// # Example
+///
+/// ```rust
+/// use account::guard::{RequireRank, rank::Moderator};
+///
+/// #[post("/thread/<id>/restore")]
+/// pub async fn thread_restore(id: &str, authed: RequireRank<Moderator>) { .. }
+/// ```
+pub struct RequireRank<R: MinRank> {
+    authed: AuthedAccount,
+    rank: PhantomData<R>
+}
+
+impl<R: MinRank> Deref for RequireRank<R> {
+    type Target = AuthedAccount;
+
+    fn deref(&self) -> &Self::Target {
+        &self.authed
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, R: MinRank> FromRequest<'r> for RequireRank<R> {
+    type Error = AccountError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let authed = try_outcome!(req.guard::<AuthedAccount>().await);
+        match authed.require(R::PERMISSION) {
+            Ok(_) => Outcome::Success(RequireRank { authed, rank: PhantomData }),
+            Err(er) => fail(req, er),
+        }
+    }
+}
+
+/// Fails a guard and leaves the error for [`crate::error::default_catcher`],
+/// which otherwise only knows the status.
+pub(super) fn fail<T>(req: &Request<'_>, er: AccountError) -> Outcome<T, AccountError> {
+    req.local_cache(|| Some(ApiError::from(er.clone())));
+    Outcome::Error((er.status(), er))
+}
@@ -0,0 +1,158 @@
+use std::sync::{Arc, OnceLock};
+
+use argon2::{Argon2, Params, Version};
+use nanoid::nanoid;
+use pbkdf2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString},
+    Pbkdf2
+};
+use rocket::tokio::task;
+
+/// Hashes and checks account passwords, picked with `[app.password]`
+/// in Rocket.toml (see [`crate::config::PasswordConfig`]).
+///
+/// Hashes are PHC strings (`$argon2id$v=19$m=19456,t=2,p=1$..`), so
+/// every hasher can still check the ones an older setup stored and
+/// [`AccountConfig::auth`](super::config::AccountConfig::auth) replaces
+/// those once the password is known.
+pub trait PasswordHasher: Send + Sync {
+    /// Hashes `password` with a fresh salt.
+    fn hash(&self, password: &str) -> String;
+
+    /// Whether `hash` was made with another algorithm or other
+    /// parameters than this hasher uses.
+    fn needs_rehash(&self, hash: &str) -> bool;
+
+    /// The hash of a password nobody knows, made like any other. Logins
+    /// for unknown accounts are checked against it so they take as long
+    /// as a wrong password.
+    fn dummy_hash(&self) -> &str;
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        verify(password, hash)
+    }
+}
+
+/// Checks `password` against an argon2 or pbkdf2 PHC string, unknown
+/// or broken hashes never match.
+pub fn verify(password: &str, hash: &str) -> bool {
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return false,
+    };
+    match parsed.algorithm.as_str() {
+        "argon2id" | "argon2i" | "argon2d" => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+        "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Pbkdf2.verify_password(password.as_bytes(), &parsed).is_ok(),
+        _ => false,
+    }
+}
+
+/// [`PasswordHasher::hash`] on tokio's blocking threads, an Argon2id
+/// hash takes long enough to stall every other request on the worker.
+pub async fn hash_blocking(hasher: &Arc<dyn PasswordHasher>, password: &str) -> String {
+    let (hasher, password) = (hasher.clone(), password.to_string());
+    task::spawn_blocking(move || hasher.hash(&password)).await
+        .expect("hashing a password never panics")
+}
+
+/// [`PasswordHasher::verify`] on tokio's blocking threads, see
+/// [`hash_blocking`].
+pub async fn verify_blocking(hasher: &Arc<dyn PasswordHasher>, password: &str, hash: &str) -> bool {
+    let (hasher, password, hash) = (hasher.clone(), password.to_string(), hash.to_string());
+    task::spawn_blocking(move || hasher.verify(&password, &hash)).await.unwrap_or(false)
+}
+
+/// Checks `password` against [`PasswordHasher::dummy_hash`] on tokio's
+/// blocking threads, never matches.
+pub async fn verify_dummy_blocking(hasher: &Arc<dyn PasswordHasher>, password: &str) {
+    let (hasher, password) = (hasher.clone(), password.to_string());
+    let _ = task::spawn_blocking(move || hasher.verify(&password, hasher.dummy_hash())).await;
+}
+
+/// The default, Argon2id.
+pub struct Argon2idHasher {
+    argon2: Argon2<'static>,
+    dummy: OnceLock<String>
+}
+
+impl Argon2idHasher {
+    pub fn new(params: Params) -> Self {
+        Argon2idHasher {
+            argon2: Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params),
+            dummy: OnceLock::new()
+        }
+    }
+}
+
+impl PasswordHasher for Argon2idHasher {
+    fn hash(&self, password: &str) -> String {
+        self.argon2.hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .expect("argon2 params are checked on startup")
+            .to_string()
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return true,
+        };
+        if parsed.algorithm.as_str() != "argon2id" || parsed.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+        let wanted = self.argon2.params();
+        match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() != wanted.m_cost()
+                || params.t_cost() != wanted.t_cost()
+                || params.p_cost() != wanted.p_cost()
+            },
+            Err(_) => true,
+        }
+    }
+
+    fn dummy_hash(&self) -> &str {
+        self.dummy.get_or_init(|| self.hash(&nanoid!(32)))
+    }
+}
+
+/// PBKDF2-SHA256, for setups that can't spare the memory Argon2id needs.
+pub struct Pbkdf2Hasher {
+    params: pbkdf2::Params,
+    dummy: OnceLock<String>
+}
+
+impl Pbkdf2Hasher {
+    pub fn new(rounds: u32) -> Self {
+        Pbkdf2Hasher {
+            params: pbkdf2::Params { rounds, ..Default::default() },
+            dummy: OnceLock::new()
+        }
+    }
+}
+
+impl PasswordHasher for Pbkdf2Hasher {
+    fn hash(&self, password: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        Pbkdf2.hash_password_customized(password.as_bytes(), Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()), None, self.params, &salt)
+            .expect("pbkdf2 params are checked on startup")
+            .to_string()
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return true,
+        };
+        if parsed.algorithm.as_str() != "pbkdf2-sha256" {
+            return true;
+        }
+        match pbkdf2::Params::try_from(&parsed) {
+            Ok(params) => params.rounds != self.params.rounds || params.output_length != self.params.output_length,
+            Err(_) => true,
+        }
+    }
+
+    fn dummy_hash(&self) -> &str {
+        self.dummy.get_or_init(|| self.hash(&nanoid!(32)))
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::{request::{FromRequest, Outcome}, Request};
+use tokio_postgres::Row;
+
+use crate::{config::{AppConfig, LoginConfig}, mail::{Mail, Mailer}};
+
+use super::{config::{Account, AccountConfig}, error::AccountError, guard::fail, onetime::Purpose};
+
+/// The failed logins on one key, an account (`account:{id}`) or an ip
+/// (`ip:{ip}`), or the mails an ip asked for (`mail:{ip}`). Attempts are counted before their password is checked
+/// and taken back once it was right, a successful login clears the
+/// account's.
+#[derive(Debug, Clone)]
+pub struct LoginAttempts {
+    pub attempt_key: String,
+    pub failures: i32,
+    /// Unix time (in ms) of the latest counted attempt.
+    pub last_failure: String
+}
+
+/// What [`LoginAttemptRepository::reserve`](super::repository::LoginAttemptRepository::reserve)
+/// decided about an attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attempt {
+    /// Counted, the failures so far including this attempt.
+    Allowed(u32),
+    /// Not counted, the key has to wait this many more seconds.
+    Wait(u64)
+}
+
+impl LoginAttempts {
+    pub fn account_key(account_id: &str) -> String {
+        format!("account:{}", account_id)
+    }
+
+    pub fn ip_key(ip: &str) -> String {
+        format!("ip:{}", ip)
+    }
+
+    pub fn mail_key(ip: &str) -> String {
+        format!("mail:{}", ip)
+    }
+
+    /// How long the next login has to wait after `failures` failed ones,
+    /// in seconds. The first `free` failures cost nothing, every one
+    /// after that doubles the wait up to `max_delay`.
+    pub fn delay(failures: u32, free: u32, limits: &LoginConfig) -> u64 {
+        if failures < free {
+            return 0;
+        }
+        let doubled = 1u64.checked_shl(failures - free).unwrap_or(u64::MAX);
+        limits.base_delay.saturating_mul(doubled).min(limits.max_delay)
+    }
+
+    /// Seconds left until the next login is allowed, `None` if it is.
+    pub fn retry_after(&self, free: u32, limits: &LoginConfig) -> Option<u64> {
+        let last_failure = self.last_failure.parse::<u128>().ok()?;
+        let delay = LoginAttempts::delay(self.failures.max(0) as u32, free, limits) as u128 * 1000;
+        let wait = (last_failure + delay).checked_sub(LoginAttempts::now())?;
+        Some(wait.div_ceil(1000) as u64).filter(|secs| *secs > 0)
+    }
+
+    pub fn now() -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+    }
+}
+
+impl From<&Row> for LoginAttempts {
+    fn from(value: &Row) -> Self {
+        LoginAttempts {
+            attempt_key: value.get(0),
+            failures: value.get(1),
+            last_failure: value.get(2)
+        }
+    }
+}
+
+/// Slows down password guessing, see `[app.login]` in
+/// [`crate::config::LoginConfig`]. Handed to
+/// [`AccountConfig::auth`], which has it count the attempt before
+/// checking a password and tells it how the check went. Counting first
+/// means parallel guesses get no more tries than sequential ones.
+///
+/// Too many failures are answered with a 429 and `Retry-After`. Once an
+/// account reaches `max_delay` its owner gets a mail with an unlock link,
+/// resetting the password unlocks it as well.
+///
+/// # Example
+///
+/// ```rust
+/// #[post("/account/login", data = "<login>")]
+/// pub async fn account_login(login: Form<AccountLogin>, limiter: LoginLimiter<'_>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+///     let session = AccountConfig::new(repos).auth(login.method(), &login.identifier, &login.password, &limiter).await?;
+///     ..
+/// }
+/// ```
+pub struct LoginLimiter<'r> {
+    limits: &'r LoginConfig,
+    frontend_origin: &'r str,
+    mailer: &'r dyn Mailer,
+    ip: Option<String>
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginLimiter<'r> {
+    type Error = AccountError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (app, mailer) = match (req.rocket().state::<AppConfig>(), req.rocket().state::<Box<dyn Mailer>>()) {
+            (Some(app), Some(mailer)) => (app, mailer),
+            _ => return fail(req, AccountError::Database("no config or mailer is managed".to_string())),
+        };
+        Outcome::Success(LoginLimiter {
+            limits: &app.login,
+            frontend_origin: &app.frontend_origin,
+            mailer: mailer.as_ref(),
+            ip: app.client_ip(req).map(|ip| ip.to_string())
+        })
+    }
+}
+
+impl LoginLimiter<'_> {
+    /// Counts an attempt against the ip, fails with
+    /// [`AccountError::TooManyRequests`] while it has to wait.
+    pub async fn reserve_ip(&self, cfg: &AccountConfig<'_>) -> Result<(), AccountError> {
+        match &self.ip {
+            Some(ip) => self.reserve(cfg, &LoginAttempts::ip_key(ip), self.limits.ip_free_attempts).await.map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts an attempt against the account before its password is even
+    /// looked at, fails with [`AccountError::TooManyRequests`] while it
+    /// has to wait (the ip's attempt is given back then). Returns the
+    /// failures so far for [`LoginLimiter::failed`].
+    pub async fn reserve_account(&self, cfg: &AccountConfig<'_>, acc: &Account) -> Result<u32, AccountError> {
+        let reserved = self.reserve(cfg, &LoginAttempts::account_key(acc.id()), self.limits.free_attempts).await;
+        if reserved.is_err() {
+            self.release_ip(cfg).await?;
+        }
+        reserved
+    }
+
+    /// Counts a reset or login link mail against the ip, fails with
+    /// [`AccountError::TooManyRequests`] while it has to wait. Never
+    /// given back, the count only tells how many mails were asked for.
+    pub async fn reserve_mail(&self, cfg: &AccountConfig<'_>) -> Result<(), AccountError> {
+        match &self.ip {
+            Some(ip) => self.reserve(cfg, &LoginAttempts::mail_key(ip), self.limits.ip_free_mails).await.map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    async fn reserve(&self, cfg: &AccountConfig<'_>, key: &str, free: u32) -> Result<u32, AccountError> {
+        match cfg.repos.attempts.reserve(key, free, self.limits).await? {
+            Attempt::Allowed(failures) => Ok(failures),
+            Attempt::Wait(retry_after) => Err(AccountError::TooManyRequests(retry_after)),
+        }
+    }
+
+    /// The password (or code) was wrong, the attempts stay counted.
+    /// Mails the owner once the account is locked.
+    pub async fn failed(&self, cfg: &AccountConfig<'_>, acc: &Account, failures: u32) {
+        let free = self.limits.free_attempts;
+        let max_delay = self.limits.max_delay;
+        if LoginAttempts::delay(failures, free, self.limits) == max_delay
+            && LoginAttempts::delay(failures.saturating_sub(1), free, self.limits) < max_delay {
+            println!("[Account] {} is locked after {} failed logins", acc.id(), failures);
+            self.notify(cfg, acc, failures).await;
+        }
+    }
+
+    /// The password was right, gives back the attempts counted on the ip
+    /// and the account. Accounts with 2FA keep their earlier failures
+    /// until the code was right too.
+    pub async fn passed(&self, cfg: &AccountConfig<'_>, acc: &Account) -> Result<(), AccountError> {
+        self.release_ip(cfg).await?;
+        cfg.repos.attempts.release(&LoginAttempts::account_key(acc.id())).await
+    }
+
+    async fn release_ip(&self, cfg: &AccountConfig<'_>) -> Result<(), AccountError> {
+        match &self.ip {
+            Some(ip) => cfg.repos.attempts.release(&LoginAttempts::ip_key(ip)).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets the account's failures, the ip's are left to expire.
+    pub async fn succeeded(&self, cfg: &AccountConfig<'_>, acc: &Account) -> Result<(), AccountError> {
+        cfg.unlock(acc).await
+    }
+
+    /// Never fails the login, a lost mail only costs the unlock link.
+    async fn notify(&self, cfg: &AccountConfig<'_>, acc: &Account, failures: u32) {
+        let token = match cfg.issue_token(acc, Purpose::Unlock).await {
+            Ok(token) => token,
+            Err(er) => {
+                println!("[Account] Could not issue the unlock link of {}: {}", acc.id(), er);
+                return;
+            },
+        };
+        let wait = match self.limits.max_delay {
+            secs if secs >= 120 => format!("{} minutes", secs / 60),
+            secs => format!("{} seconds", secs),
+        };
+        let mail = Mail {
+            to: acc.email().to_string(),
+            subject: "Your account was locked".to_string(),
+            body: format!(
+                "Hi {},\n\nthere were {} failed logins on your account, so logging in is blocked for the next {}.\n\nIf that was you, open\n\n{}/unlock?token={}\n\nto unlock it right away. If it wasn't, consider resetting your password.",
+                acc.username(), failures, wait, self.frontend_origin.trim_end_matches('/'), token
+            )
+        };
+        if let Err(er) = self.mailer.send(mail).await {
+            println!("[Account] Could not mail the unlock link of {}: {}", acc.id(), er);
+        }
+    }
+}
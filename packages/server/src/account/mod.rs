@@ -1,4 +1,11 @@
 pub mod config;
 pub mod enums;
 pub mod error;
-pub mod routes;
\ No newline at end of file
+pub mod guard;
+pub mod hasher;
+pub mod lockout;
+pub mod oidc;
+pub mod onetime;
+pub mod repository;
+pub mod routes;
+pub mod twofactor;
\ No newline at end of file
@@ -0,0 +1,331 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rand_core::{OsRng, RngCore};
+use reqwest::{StatusCode, Url};
+use rocket::{
+    http::{Cookie, CookieJar, SameSite},
+    serde::{de::DeserializeOwned, json, Deserialize, Serialize},
+    time,
+    tokio::sync::RwLock
+};
+use sha2::{Digest, Sha256};
+use tokio_postgres::Row;
+
+use crate::{config::OidcConfig, session::cookie::CookieConfig};
+
+use super::error::AccountError;
+
+/// An account linked to the subject of an OpenID Connect provider, see
+/// [`AccountConfig::oidc_account`](super::config::AccountConfig::oidc_account).
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub account_id: String,
+    /// Unix time (in ms) of the first login with the provider.
+    pub linked_at: String
+}
+
+impl ExternalIdentity {
+    pub fn new(issuer: &str, subject: &str, account_id: &str) -> Self {
+        ExternalIdentity {
+            issuer: issuer.to_string(),
+            subject: subject.to_string(),
+            account_id: account_id.to_string(),
+            linked_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string()
+        }
+    }
+}
+
+impl From<&Row> for ExternalIdentity {
+    fn from(value: &Row) -> Self {
+        ExternalIdentity {
+            issuer: value.get(0),
+            subject: value.get(1),
+            account_id: value.get(2),
+            linked_at: value.get(3)
+        }
+    }
+}
+
+/// The claims of a validated id token that matter here.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>
+}
+
+/// A login between the redirect to the provider and its callback, kept
+/// in a private (encrypted) cookie so the browser that started it has
+/// to finish it.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OidcFlow {
+    pub state: String,
+    nonce: String,
+    /// The PKCE code verifier, only its hash is sent to the provider.
+    verifier: String
+}
+
+impl OidcFlow {
+    /// Name of the cookie holding the flow.
+    pub const COOKIE: &'static str = "oidc";
+    /// How long the provider may take to send the browser back, in seconds.
+    const LIFETIME: i64 = 600;
+
+    pub fn new() -> Self {
+        OidcFlow {
+            state: OidcFlow::random(),
+            nonce: OidcFlow::random(),
+            verifier: OidcFlow::random()
+        }
+    }
+
+    /// The S256 code challenge of the verifier.
+    fn challenge(&self) -> String {
+        BASE64URL_NOPAD.encode(&Sha256::digest(self.verifier.as_bytes()))
+    }
+
+    /// Hands out the cookie. It's always `SameSite=Lax`, the provider
+    /// sends the browser back with a cross site redirect.
+    pub fn add(&self, jar: &CookieJar<'_>, cookies: &CookieConfig) {
+        let value = json::to_string(self).expect("the flow is plain strings");
+        jar.add_private(Cookie::build((OidcFlow::COOKIE, value))
+            .path("/api/account/oidc")
+            .secure(cookies.secure)
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(OidcFlow::LIFETIME)));
+    }
+
+    /// Reads and removes the cookie, a flow is only ever finished once.
+    pub fn take(jar: &CookieJar<'_>) -> Option<OidcFlow> {
+        let cookie = jar.get_private(OidcFlow::COOKIE)?;
+        jar.remove_private(Cookie::build(OidcFlow::COOKIE).path("/api/account/oidc"));
+        json::from_str(cookie.value()).ok()
+    }
+
+    fn random() -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        BASE64URL_NOPAD.encode(&bytes)
+    }
+}
+
+/// The parts of `/.well-known/openid-configuration` used here.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    token_endpoint_auth_methods_supported: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TokenResponse {
+    id_token: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TokenError {
+    error: String
+}
+
+/// Signs accounts in with the provider of `[app.oidc]` (see
+/// [`crate::config::OidcConfig`]), using the authorization code flow with
+/// PKCE. Managed by rocket.
+///
+/// The discovery document and the provider's keys are fetched on first
+/// use and cached, the keys again whenever a token is signed with one
+/// that isn't known yet.
+///
+/// # Example
+///
+/// ```rust
+/// let flow = OidcFlow::new();
+/// let url = oidc.authorize_url(&flow).await?; // the browser goes there
+/// ..
+/// let claims = oidc.exchange(&code, &flow).await?; // from the callback
+/// ```
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    keys: RwLock<(JwkSet, Option<Instant>)>
+}
+
+impl OidcClient {
+    /// The keys are fetched at most this often, tokens with an unknown
+    /// key can't make us hammer the provider.
+    const KEYS_REFRESH: Duration = Duration::from_secs(60);
+    /// Clock skew allowed when checking `exp`, `iat` and `nbf`, in seconds.
+    const LEEWAY: u64 = 60;
+
+    pub fn new(config: &OidcConfig) -> Self {
+        OidcClient {
+            config: config.clone(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("the default tls backend is available"),
+            metadata: RwLock::new(None),
+            keys: RwLock::new((JwkSet { keys: vec![] }, None))
+        }
+    }
+
+    /// Fails with [`AccountError::OidcDisabled`] unless `[app.oidc]` is enabled.
+    pub fn check_enabled(&self) -> Result<(), AccountError> {
+        match self.config.enabled {
+            true => Ok(()),
+            false => Err(AccountError::OidcDisabled),
+        }
+    }
+
+    /// Where to send the browser to log in, the flow has to be handed
+    /// out with [`OidcFlow::add`] as well.
+    pub async fn authorize_url(&self, flow: &OidcFlow) -> Result<String, AccountError> {
+        self.check_enabled()?;
+        let metadata = self.metadata().await?;
+        let url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("scope", self.config.scopes.as_str()),
+            ("state", flow.state.as_str()),
+            ("nonce", flow.nonce.as_str()),
+            ("code_challenge", flow.challenge().as_str()),
+            ("code_challenge_method", "S256")
+        ]).map_err(|er| OidcClient::unavailable("authorization_endpoint", er))?;
+        Ok(url.to_string())
+    }
+
+    /// Trades the code from the callback for an id token and validates
+    /// it: signature, issuer, audience, expiry and nonce.
+    pub async fn exchange(&self, code: &str, flow: &OidcFlow) -> Result<IdTokenClaims, AccountError> {
+        self.check_enabled()?;
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("code_verifier", flow.verifier.as_str()),
+            ("client_id", self.config.client_id.as_str())
+        ];
+        let mut request = self.http.post(&metadata.token_endpoint);
+        if let Some(secret) = &self.config.client_secret {
+            // client_secret_basic is what the spec defaults to.
+            let methods = &metadata.token_endpoint_auth_methods_supported;
+            if methods.is_empty() || methods.iter().any(|method| method == "client_secret_basic") {
+                request = request.basic_auth(&self.config.client_id, Some(secret));
+            } else {
+                form.push(("client_secret", secret.as_str()));
+            }
+        }
+        let res = request.form(&form).send().await.map_err(|er| OidcClient::unavailable("token_endpoint", er))?;
+        if res.status().is_client_error() {
+            // e.g. invalid_grant for a code that was used already.
+            let reason = match res.json::<TokenError>().await {
+                Ok(er) => er.error,
+                Err(_) => "the code was rejected".to_string(),
+            };
+            return Err(AccountError::OidcInvalid(reason));
+        }
+        let tokens: TokenResponse = res.error_for_status()
+            .map_err(|er| OidcClient::unavailable("token_endpoint", er))?
+            .json().await
+            .map_err(|er| OidcClient::unavailable("token_endpoint", er))?;
+        let id_token = tokens.id_token.ok_or_else(|| AccountError::OidcInvalid("the provider sent no id token".to_string()))?;
+        self.validate(&id_token, &metadata, flow).await
+    }
+
+    async fn validate(&self, id_token: &str, metadata: &ProviderMetadata, flow: &OidcFlow) -> Result<IdTokenClaims, AccountError> {
+        let invalid = |reason: &str| AccountError::OidcInvalid(reason.to_string());
+        let header = decode_header(id_token).map_err(|_| invalid("the id token is malformed"))?;
+        // only signatures made with the provider's private key, never
+        // `none` or a shared secret.
+        if !matches!(header.alg,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+            | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512
+            | Algorithm::ES256 | Algorithm::ES384 | Algorithm::EdDSA
+        ) {
+            return Err(invalid("the id token is signed with an unsupported algorithm"));
+        }
+        let key = self.key(header.kid.as_deref(), &metadata.jwks_uri).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = OidcClient::LEEWAY;
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|er| AccountError::OidcInvalid(format!("the id token is invalid ({})", er)))?
+            .claims;
+        if claims.nonce.as_deref() != Some(flow.nonce.as_str()) {
+            return Err(invalid("the id token belongs to another login"));
+        }
+        Ok(claims)
+    }
+
+    /// The provider's key with the given id, tokens without one need
+    /// the provider to have a single key.
+    async fn key(&self, kid: Option<&str>, jwks_uri: &str) -> Result<DecodingKey, AccountError> {
+        let find = |keys: &JwkSet| match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        };
+        let cached = self.keys.read().await;
+        let mut jwk = find(&cached.0);
+        let fresh = cached.1.is_some_and(|fetched| fetched.elapsed() < OidcClient::KEYS_REFRESH);
+        drop(cached);
+
+        if jwk.is_none() && !fresh {
+            let keys: JwkSet = self.fetch("jwks_uri", jwks_uri).await?;
+            jwk = find(&keys);
+            *self.keys.write().await = (keys, Some(Instant::now()));
+        }
+        let jwk = jwk.ok_or_else(|| AccountError::OidcInvalid("the id token is signed with an unknown key".to_string()))?;
+        DecodingKey::from_jwk(&jwk).map_err(|er| OidcClient::unavailable("jwks_uri", er))
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, AccountError> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+        let issuer = self.config.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata = self.fetch("discovery", &format!("{}/.well-known/openid-configuration", issuer)).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(OidcClient::unavailable("discovery", format!("the document is for {}", metadata.issuer)));
+        }
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, what: &str, url: &str) -> Result<T, AccountError> {
+        let res = self.http.get(url).send().await.map_err(|er| OidcClient::unavailable(what, er))?;
+        if res.status() != StatusCode::OK {
+            return Err(OidcClient::unavailable(what, format!("answered with {}", res.status())));
+        }
+        res.json().await.map_err(|er| OidcClient::unavailable(what, er))
+    }
+
+    /// Logs what went wrong, the client only learns the provider failed.
+    fn unavailable(what: &str, er: impl ToString) -> AccountError {
+        let message = format!("{}: {}", what, er.to_string());
+        println!("[Oidc] {}", message);
+        AccountError::OidcUnavailable(message)
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fmt, time::{SystemTime, UNIX_EPOCH}};
+
+use nanoid::nanoid;
+use tokio_postgres::Row;
+
+use crate::session::config::Session;
+
+/// What a [`OneTimeToken`] may be redeemed for, a token is never valid
+/// for anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    PasswordReset,
+    EmailVerification,
+    Unlock,
+    /// The second step of a login with 2FA.
+    TwoFactorLogin,
+    /// A passwordless login link.
+    MagicLink
+}
+
+impl Purpose {
+    /// How long a token stays valid, in ms.
+    pub fn lifetime(&self) -> u128 {
+        match self {
+            Purpose::PasswordReset => 3600000, // one hour
+            Purpose::EmailVerification | Purpose::Unlock => 86400000, // one day
+            Purpose::TwoFactorLogin => 300000, // five minutes
+            Purpose::MagicLink => 900000, // 15 minutes
+        }
+    }
+}
+
+impl fmt::Display for Purpose {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            Purpose::PasswordReset => write!(f, "password_reset"),
+            Purpose::EmailVerification => write!(f, "email_verification"),
+            Purpose::Unlock => write!(f, "unlock"),
+            Purpose::TwoFactorLogin => write!(f, "two_factor_login"),
+            Purpose::MagicLink => write!(f, "magic_link"),
+        }
+    }
+}
+
+/// A single use, expiring token mailed to an account, e.g. for a
+/// password reset link. Only the sha256 of the token is stored and it's
+/// deleted once redeemed.
+#[derive(Debug, Clone)]
+pub struct OneTimeToken {
+    pub token_hash: String,
+    pub account_id: String,
+    pub purpose: String,
+    pub created_at: String,
+    pub expires_in: String
+}
+
+impl OneTimeToken {
+    /// How long an account has to wait before another token with the
+    /// same purpose is mailed, in ms.
+    pub const RESEND_INTERVAL: u128 = 60000; // one minute
+
+    /// Constructs a new [`OneTimeToken`] and returns it together with
+    /// the plain token, which only ever ends up in the mail.
+    pub fn new(account_id: &str, purpose: Purpose) -> (OneTimeToken, String) {
+        let now = OneTimeToken::now();
+        let token = nanoid!(48);
+        let one_time = OneTimeToken {
+            token_hash: Session::hash(&token),
+            account_id: account_id.to_string(),
+            purpose: purpose.to_string(),
+            created_at: now.to_string(),
+            expires_in: (now + purpose.lifetime()).to_string()
+        };
+        (one_time, token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_in.parse::<u128>().map_or(true, |expires_in| expires_in <= OneTimeToken::now())
+    }
+
+    /// Seconds until [`OneTimeToken::RESEND_INTERVAL`] has passed, `None`
+    /// once it has.
+    pub fn resend_after(&self) -> Option<u64> {
+        let created_at = self.created_at.parse::<u128>().ok()?;
+        let wait = (created_at + OneTimeToken::RESEND_INTERVAL).checked_sub(OneTimeToken::now())?;
+        Some(wait.div_ceil(1000) as u64).filter(|secs| *secs > 0)
+    }
+
+    fn now() -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+    }
+}
+
+impl From<&Row> for OneTimeToken {
+    fn from(value: &Row) -> Self {
+        OneTimeToken {
+            token_hash: value.get(0),
+            account_id: value.get(1),
+            purpose: value.get(2),
+            created_at: value.get(3),
+            expires_in: value.get(4)
+        }
+    }
+}
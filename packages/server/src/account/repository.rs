@@ -0,0 +1,747 @@
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use postgres_types::ToSql;
+
+use crate::{config::LoginConfig, db, repository::SharedStore};
+
+use super::{
+    config::{Account, AccountUpdate}, enums::{LoginMethod, Rank}, error::AccountError, lockout::{Attempt, LoginAttempts},
+    oidc::ExternalIdentity, onetime::{OneTimeToken, Purpose}, twofactor::TwoFactor
+};
+
+/// Stores and looks up accounts, see [`crate::repository::Repositories`].
+///
+/// Every implementation answers with the same [`AccountError`]s so the
+/// routes can't tell them apart.
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+    /// Fails with [`AccountError::UsernameTaken`] or
+    /// [`AccountError::EmailTaken`], both compared case insensitive.
+    async fn create(&self, acc: &Account) -> Result<(), AccountError>;
+
+    /// Available fields: **id**, **username** and **email**.
+    async fn find(&self, field: &str, value: &str) -> Result<Account, AccountError>;
+
+    /// Finds the account someone is logging in as, the key is matched
+    /// exactly but case insensitive. Should two accounts only differ in
+    /// case, the exact match wins.
+    async fn find_login(&self, method: &LoginMethod, key: &str) -> Result<Option<Account>, AccountError>;
+
+    /// Fails like [`AccountRepository::create`], the account may change
+    /// the case of its own username or email. A changed email drops the
+    /// confirmation links mailed so far, with `unconfirm` the account also
+    /// falls back to [`Rank::None`].
+    async fn update(&self, acc: &Account, update: &AccountUpdate, unconfirm: bool) -> Result<Account, AccountError>;
+
+    /// Also ends every session and token of the account, its threads are
+    /// kept without an author.
+    async fn delete(&self, acc: &Account) -> Result<(), AccountError>;
+
+    async fn set_rank(&self, acc: &Account, rank: Rank) -> Result<Account, AccountError>;
+
+    /// Records the current email as confirmed, a None account becomes a
+    /// Member.
+    async fn confirm_email(&self, acc: &Account) -> Result<Account, AccountError>;
+
+    /// Replaces the stored password hash.
+    async fn set_password(&self, acc: &Account, hash: &str) -> Result<(), AccountError>;
+
+    /// Banning also ends every session and token of the account.
+    async fn set_banned(&self, acc: &Account, banned: bool) -> Result<Account, AccountError>;
+
+    /// Replaces the stored password hash and drops everything else that
+    /// logs into the account: sessions, access and one-time tokens, 2FA
+    /// and linked external identities.
+    async fn reclaim(&self, acc: &Account, hash: &str) -> Result<(), AccountError>;
+}
+
+pub struct PgAccountRepository {
+    pool: Pool
+}
+
+impl PgAccountRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgAccountRepository { pool }
+    }
+
+    /// Runs a function returning `setof accounts` and reads the first row.
+    async fn query_one(&self, sql: &str, acc: &Account, params: &[&(dyn ToSql + Sync)]) -> Result<Account, AccountError> {
+        match db::query(&self.pool, sql, params).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Account::from(row)),
+                None => Err(AccountError::AccountNotFound(acc.id().to_string()))
+            },
+            Err(er) => Err(AccountError::parse_db_error(&er, acc)),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountRepository for PgAccountRepository {
+    async fn create(&self, acc: &Account) -> Result<(), AccountError> {
+        let sql = "SELECT create_account($1, $2, $3, $4, $5)";
+        match db::query(&self.pool, sql, &[acc.id(), acc.username(), acc.email(), acc.password(), acc.rank()]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, acc)),
+        }
+    }
+
+    async fn find(&self, field: &str, value: &str) -> Result<Account, AccountError> {
+        let sql = format!("select * from find_by_{}($1)", field);
+        match self.query_one(&sql, &Account::default(), &[&value]).await {
+            Err(AccountError::AccountNotFound(_)) => Err(AccountError::AccountNotFound(value.to_string())),
+            res => res,
+        }
+    }
+
+    async fn find_login(&self, method: &LoginMethod, key: &str) -> Result<Option<Account>, AccountError> {
+        // the column comes from the enum, never from the request.
+        let sql = format!("SELECT * from accounts where lower({0}) = lower($1) ORDER BY {0} = $1 DESC LIMIT 1", method);
+        match db::query(&self.pool, &sql, &[&key]).await {
+            Ok(res) => Ok(res.first().map(Account::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn update(&self, acc: &Account, update: &AccountUpdate, unconfirm: bool) -> Result<Account, AccountError> {
+        let mut changed = acc.clone();
+        changed.username = update.username.clone().unwrap_or(changed.username);
+        changed.email = update.email.clone().unwrap_or(changed.email);
+        self.query_one("select * from update_account($1, $2, $3, $4)", &changed, &[acc.id(), &update.username, &update.email, &unconfirm]).await
+    }
+
+    async fn delete(&self, acc: &Account) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select delete_account($1)", &[acc.id()]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, acc)),
+        }
+    }
+
+    async fn set_rank(&self, acc: &Account, rank: Rank) -> Result<Account, AccountError> {
+        self.query_one("select * from set_rank($1, $2)", acc, &[acc.id(), &rank]).await
+    }
+
+    async fn confirm_email(&self, acc: &Account) -> Result<Account, AccountError> {
+        self.query_one("select * from confirm_email($1)", acc, &[acc.id()]).await
+    }
+
+    async fn set_password(&self, acc: &Account, hash: &str) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select set_password($1, $2)", &[acc.id(), &hash]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, acc)),
+        }
+    }
+
+    async fn set_banned(&self, acc: &Account, banned: bool) -> Result<Account, AccountError> {
+        self.query_one("select * from set_banned($1, $2)", acc, &[acc.id(), &banned]).await
+    }
+
+    async fn reclaim(&self, acc: &Account, hash: &str) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select reclaim_account($1, $2)", &[acc.id(), &hash]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, acc)),
+        }
+    }
+}
+
+pub struct MemoryAccountRepository {
+    store: SharedStore
+}
+
+impl MemoryAccountRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryAccountRepository { store }
+    }
+}
+
+#[async_trait]
+impl AccountRepository for MemoryAccountRepository {
+    async fn create(&self, acc: &Account) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        if store.accounts.iter().any(|other| other.username.to_lowercase() == acc.username.to_lowercase()) {
+            return Err(AccountError::UsernameTaken(acc.username.clone()));
+        }
+        if store.accounts.iter().any(|other| other.email.to_lowercase() == acc.email.to_lowercase()) {
+            return Err(AccountError::EmailTaken(acc.email.clone()));
+        }
+        store.accounts.push(acc.clone());
+        Ok(())
+    }
+
+    async fn find(&self, field: &str, value: &str) -> Result<Account, AccountError> {
+        let store = self.store.lock();
+        let found = store.accounts.iter().find(|acc| match field {
+            "id" => acc.id == value,
+            "username" => acc.username == value,
+            "email" => acc.email == value,
+            _ => false,
+        });
+        match found {
+            Some(acc) => Ok(acc.clone()),
+            None => Err(AccountError::AccountNotFound(value.to_string())),
+        }
+    }
+
+    async fn find_login(&self, method: &LoginMethod, key: &str) -> Result<Option<Account>, AccountError> {
+        let store = self.store.lock();
+        let field = |acc: &Account| match method {
+            LoginMethod::Username => acc.username.clone(),
+            LoginMethod::Email => acc.email.clone(),
+        };
+        let matches: Vec<&Account> = store.accounts.iter()
+            .filter(|acc| field(acc).to_lowercase() == key.to_lowercase())
+            .collect();
+        let exact = matches.iter().find(|acc| field(acc) == key);
+        Ok(exact.or(matches.first()).map(|acc| (*acc).clone()))
+    }
+
+    async fn update(&self, acc: &Account, update: &AccountUpdate, unconfirm: bool) -> Result<Account, AccountError> {
+        let mut store = self.store.lock();
+        if let Some(username) = &update.username {
+            if store.accounts.iter().any(|other| other.id != acc.id && other.username.to_lowercase() == username.to_lowercase()) {
+                return Err(AccountError::UsernameTaken(username.clone()));
+            }
+        }
+        if let Some(email) = &update.email {
+            if store.accounts.iter().any(|other| other.id != acc.id && other.email.to_lowercase() == email.to_lowercase()) {
+                return Err(AccountError::EmailTaken(email.clone()));
+            }
+        }
+        let changes_email = update.email.as_ref().is_some_and(|email| store.accounts.iter()
+            .any(|other| other.id == acc.id && other.email.to_lowercase() != email.to_lowercase()));
+        if changes_email {
+            let purpose = Purpose::EmailVerification.to_string();
+            store.one_time.retain(|token| token.account_id != acc.id || token.purpose != purpose);
+        }
+        let stored = match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => stored,
+            None => return Err(AccountError::AccountNotFound(acc.id.clone())),
+        };
+        if let Some(username) = &update.username {
+            stored.username = username.clone();
+        }
+        if let Some(email) = &update.email {
+            if unconfirm && changes_email {
+                stored.rank = Rank::None;
+            }
+            stored.email = email.clone();
+        }
+        Ok(stored.clone())
+    }
+
+    async fn delete(&self, acc: &Account) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        if !store.accounts.iter().any(|other| other.id == acc.id) {
+            return Err(AccountError::AccountNotFound(acc.id.clone()));
+        }
+        store.accounts.retain(|other| other.id != acc.id);
+        store.sessions.retain(|session| session.account_id != acc.id);
+        store.tokens.retain(|token| token.account_id != acc.id);
+        store.one_time.retain(|token| token.account_id != acc.id);
+        store.two_factor.retain(|two_factor| two_factor.account_id != acc.id);
+        store.recovery_codes.retain(|(account_id, _)| *account_id != acc.id);
+        store.external_identities.retain(|identity| identity.account_id != acc.id);
+        for thread in store.threads.iter_mut().filter(|thread| thread.created_by.as_ref() == Some(&acc.id)) {
+            thread.created_by = None;
+        }
+        Ok(())
+    }
+
+    async fn set_rank(&self, acc: &Account, rank: Rank) -> Result<Account, AccountError> {
+        let mut store = self.store.lock();
+        match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => {
+                stored.rank = rank;
+                Ok(stored.clone())
+            },
+            None => Err(AccountError::AccountNotFound(acc.id.clone())),
+        }
+    }
+
+    async fn confirm_email(&self, acc: &Account) -> Result<Account, AccountError> {
+        let mut store = self.store.lock();
+        match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => {
+                stored.verified_email = Some(stored.email.clone());
+                if stored.rank == Rank::None {
+                    stored.rank = Rank::Member;
+                }
+                Ok(stored.clone())
+            },
+            None => Err(AccountError::AccountNotFound(acc.id.clone())),
+        }
+    }
+
+    async fn set_password(&self, acc: &Account, hash: &str) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => {
+                stored.password = hash.to_string();
+                Ok(())
+            },
+            None => Err(AccountError::AccountNotFound(acc.id.clone())),
+        }
+    }
+
+    async fn set_banned(&self, acc: &Account, banned: bool) -> Result<Account, AccountError> {
+        let mut store = self.store.lock();
+        let stored = match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => {
+                stored.banned = banned;
+                stored.clone()
+            },
+            None => return Err(AccountError::AccountNotFound(acc.id.clone())),
+        };
+        if banned {
+            store.sessions.retain(|session| session.account_id != acc.id);
+            store.tokens.retain(|token| token.account_id != acc.id);
+        }
+        Ok(stored)
+    }
+
+    async fn reclaim(&self, acc: &Account, hash: &str) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        match store.accounts.iter_mut().find(|other| other.id == acc.id) {
+            Some(stored) => stored.password = hash.to_string(),
+            None => return Err(AccountError::AccountNotFound(acc.id.clone())),
+        }
+        store.sessions.retain(|session| session.account_id != acc.id);
+        store.tokens.retain(|token| token.account_id != acc.id);
+        store.one_time.retain(|token| token.account_id != acc.id);
+        store.two_factor.retain(|two_factor| two_factor.account_id != acc.id);
+        store.recovery_codes.retain(|(account_id, _)| *account_id != acc.id);
+        store.external_identities.retain(|identity| identity.account_id != acc.id);
+        Ok(())
+    }
+}
+
+/// Stores the [`OneTimeToken`]s mailed to accounts, see
+/// [`crate::repository::Repositories`].
+#[async_trait]
+pub trait OneTimeTokenRepository: Send + Sync {
+    async fn create(&self, token: &OneTimeToken) -> Result<(), AccountError>;
+
+    /// Finds a token by its hash, expired ones included.
+    async fn find(&self, token_hash: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError>;
+
+    /// The most recently issued token of the account with this purpose.
+    async fn latest(&self, account_id: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError>;
+
+    /// Deletes a token and returns it, `None` if it was already redeemed.
+    async fn consume(&self, token_hash: &str) -> Result<Option<OneTimeToken>, AccountError>;
+
+    /// Deletes every token of the account with this purpose.
+    async fn remove_all(&self, account_id: &str, purpose: &str) -> Result<(), AccountError>;
+}
+
+pub struct PgOneTimeTokenRepository {
+    pool: Pool
+}
+
+impl PgOneTimeTokenRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgOneTimeTokenRepository { pool }
+    }
+}
+
+#[async_trait]
+impl OneTimeTokenRepository for PgOneTimeTokenRepository {
+    async fn create(&self, token: &OneTimeToken) -> Result<(), AccountError> {
+        let sql = "SELECT create_one_time_token($1, $2, $3, $4, $5)";
+        let params: [&(dyn ToSql + Sync); 5] = [&token.token_hash, &token.account_id, &token.purpose, &token.created_at, &token.expires_in];
+        match db::query(&self.pool, sql, &params).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn find(&self, token_hash: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        match db::query(&self.pool, "select * from find_one_time_token($1, $2)", &[&token_hash, &purpose]).await {
+            Ok(res) => Ok(res.first().map(OneTimeToken::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn latest(&self, account_id: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        match db::query(&self.pool, "select * from find_latest_one_time_token($1, $2)", &[&account_id, &purpose]).await {
+            Ok(res) => Ok(res.first().map(OneTimeToken::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        match db::query(&self.pool, "select * from consume_one_time_token($1)", &[&token_hash]).await {
+            Ok(res) => Ok(res.first().map(OneTimeToken::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn remove_all(&self, account_id: &str, purpose: &str) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select delete_one_time_tokens($1, $2)", &[&account_id, &purpose]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+}
+
+pub struct MemoryOneTimeTokenRepository {
+    store: SharedStore
+}
+
+impl MemoryOneTimeTokenRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryOneTimeTokenRepository { store }
+    }
+}
+
+#[async_trait]
+impl OneTimeTokenRepository for MemoryOneTimeTokenRepository {
+    async fn create(&self, token: &OneTimeToken) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        if !store.accounts.iter().any(|acc| acc.id == token.account_id) {
+            return Err(AccountError::AccountNotFound(token.account_id.clone()));
+        }
+        store.one_time.push(token.clone());
+        Ok(())
+    }
+
+    async fn find(&self, token_hash: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        let store = self.store.lock();
+        Ok(store.one_time.iter().find(|token| token.token_hash == token_hash && token.purpose == purpose).cloned())
+    }
+
+    async fn latest(&self, account_id: &str, purpose: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        let store = self.store.lock();
+        Ok(store.one_time.iter()
+            .filter(|token| token.account_id == account_id && token.purpose == purpose)
+            .max_by_key(|token| token.created_at.parse::<u128>().unwrap_or(0))
+            .cloned())
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<OneTimeToken>, AccountError> {
+        let mut store = self.store.lock();
+        match store.one_time.iter().position(|token| token.token_hash == token_hash) {
+            Some(index) => Ok(Some(store.one_time.remove(index))),
+            None => Ok(None),
+        }
+    }
+
+    async fn remove_all(&self, account_id: &str, purpose: &str) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        store.one_time.retain(|token| token.account_id != account_id || token.purpose != purpose);
+        Ok(())
+    }
+}
+
+/// Counts login attempts, see [`crate::account::lockout::LoginLimiter`].
+#[async_trait]
+pub trait LoginAttemptRepository: Send + Sync {
+    /// Counts an attempt on `key` unless it still has to wait (see
+    /// [`LoginAttempts::delay`]), checking and counting in one step so
+    /// parallel guesses can't all get through before any was counted.
+    /// Failures older than `reset_after` are forgotten first.
+    async fn reserve(&self, key: &str, free: u32, limits: &LoginConfig) -> Result<Attempt, AccountError>;
+
+    /// Takes back an attempt [`LoginAttemptRepository::reserve`] counted.
+    async fn release(&self, key: &str) -> Result<(), AccountError>;
+
+    async fn clear(&self, key: &str) -> Result<(), AccountError>;
+}
+
+pub struct PgLoginAttemptRepository {
+    pool: Pool
+}
+
+impl PgLoginAttemptRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgLoginAttemptRepository { pool }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for PgLoginAttemptRepository {
+    async fn reserve(&self, key: &str, free: u32, limits: &LoginConfig) -> Result<Attempt, AccountError> {
+        let sql = "select * from reserve_login_attempt($1, $2, $3, $4, $5, $6)";
+        let (free, base_delay, max_delay) = (free as i32, limits.base_delay as i64, limits.max_delay as i64);
+        let reset_after = (limits.reset_after as u128 * 1000).to_string();
+        match db::query(&self.pool, sql, &[&key, &LoginAttempts::now().to_string(), &free, &base_delay, &max_delay, &reset_after]).await {
+            Ok(res) => match res.first() {
+                Some(row) => match row.get::<_, i64>(1) {
+                    0 => Ok(Attempt::Allowed(row.get::<_, i32>(0).max(0) as u32)),
+                    wait => Ok(Attempt::Wait(wait as u64)),
+                },
+                None => Err(AccountError::Database("reserve_login_attempt returned nothing".to_string())),
+            },
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn release(&self, key: &str) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select release_login_attempt($1)", &[&key]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AccountError> {
+        match db::query(&self.pool, "select clear_login_attempts($1)", &[&key]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+}
+
+pub struct MemoryLoginAttemptRepository {
+    store: SharedStore
+}
+
+impl MemoryLoginAttemptRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryLoginAttemptRepository { store }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for MemoryLoginAttemptRepository {
+    async fn reserve(&self, key: &str, free: u32, limits: &LoginConfig) -> Result<Attempt, AccountError> {
+        let mut store = self.store.lock();
+        let now = LoginAttempts::now();
+        let index = match store.login_attempts.iter().position(|attempts| attempts.attempt_key == key) {
+            Some(index) => index,
+            None => {
+                store.login_attempts.push(LoginAttempts { attempt_key: key.to_string(), failures: 0, last_failure: now.to_string() });
+                store.login_attempts.len() - 1
+            },
+        };
+        let attempts = &mut store.login_attempts[index];
+        let last_failure = attempts.last_failure.parse::<u128>().unwrap_or(0);
+        if now.saturating_sub(last_failure) > limits.reset_after as u128 * 1000 {
+            attempts.failures = 0;
+        }
+        if let Some(retry_after) = attempts.retry_after(free, limits) {
+            return Ok(Attempt::Wait(retry_after));
+        }
+        attempts.failures += 1;
+        attempts.last_failure = now.to_string();
+        Ok(Attempt::Allowed(attempts.failures as u32))
+    }
+
+    async fn release(&self, key: &str) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        if let Some(attempts) = store.login_attempts.iter_mut().find(|attempts| attempts.attempt_key == key) {
+            attempts.failures = (attempts.failures - 1).max(0);
+        }
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AccountError> {
+        self.store.lock().login_attempts.retain(|attempts| attempts.attempt_key != key);
+        Ok(())
+    }
+}
+
+/// Stores TOTP secrets and recovery codes, see
+/// [`crate::account::twofactor::TwoFactor`].
+#[async_trait]
+pub trait TwoFactorRepository: Send + Sync {
+    async fn find(&self, account_id: &str) -> Result<Option<TwoFactor>, AccountError>;
+
+    /// Creates or replaces the account's secret.
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), AccountError>;
+
+    /// Marks a time step as used, false if it (or a later one) already was.
+    async fn use_step(&self, account_id: &str, step: i64) -> Result<bool, AccountError>;
+
+    /// Removes the secret and every recovery code.
+    async fn delete(&self, account_id: &str) -> Result<(), AccountError>;
+
+    /// Replaces every recovery code of the account.
+    async fn set_recovery_codes(&self, account_id: &str, hashes: &[String]) -> Result<(), AccountError>;
+
+    /// Deletes a recovery code, false if the account has no such code.
+    async fn use_recovery_code(&self, account_id: &str, hash: &str) -> Result<bool, AccountError>;
+}
+
+pub struct PgTwoFactorRepository {
+    pool: Pool
+}
+
+impl PgTwoFactorRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgTwoFactorRepository { pool }
+    }
+
+    /// Runs a function returning a boolean.
+    async fn query_bool(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<bool, AccountError> {
+        match db::query(&self.pool, sql, params).await {
+            Ok(res) => Ok(res.first().map(|row| row.get::<_, bool>(0)).unwrap_or(false)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PgTwoFactorRepository {
+    async fn find(&self, account_id: &str) -> Result<Option<TwoFactor>, AccountError> {
+        match db::query(&self.pool, "select * from find_two_factor($1)", &[&account_id]).await {
+            Ok(res) => Ok(res.first().map(TwoFactor::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), AccountError> {
+        let sql = "select save_two_factor($1, $2, $3, $4)";
+        let params: [&(dyn ToSql + Sync); 4] = [&two_factor.account_id, &two_factor.secret, &two_factor.enabled, &two_factor.last_step];
+        self.query_bool(sql, &params).await.map(|_| ())
+    }
+
+    async fn use_step(&self, account_id: &str, step: i64) -> Result<bool, AccountError> {
+        self.query_bool("select use_two_factor_step($1, $2)", &[&account_id, &step]).await
+    }
+
+    async fn delete(&self, account_id: &str) -> Result<(), AccountError> {
+        self.query_bool("select delete_two_factor($1)", &[&account_id]).await.map(|_| ())
+    }
+
+    async fn set_recovery_codes(&self, account_id: &str, hashes: &[String]) -> Result<(), AccountError> {
+        self.query_bool("select set_recovery_codes($1, $2)", &[&account_id, &hashes]).await.map(|_| ())
+    }
+
+    async fn use_recovery_code(&self, account_id: &str, hash: &str) -> Result<bool, AccountError> {
+        self.query_bool("select use_recovery_code($1, $2)", &[&account_id, &hash]).await
+    }
+}
+
+pub struct MemoryTwoFactorRepository {
+    store: SharedStore
+}
+
+impl MemoryTwoFactorRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryTwoFactorRepository { store }
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for MemoryTwoFactorRepository {
+    async fn find(&self, account_id: &str) -> Result<Option<TwoFactor>, AccountError> {
+        let store = self.store.lock();
+        Ok(store.two_factor.iter().find(|two_factor| two_factor.account_id == account_id).cloned())
+    }
+
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        if !store.accounts.iter().any(|acc| acc.id == two_factor.account_id) {
+            return Err(AccountError::AccountNotFound(two_factor.account_id.clone()));
+        }
+        store.two_factor.retain(|other| other.account_id != two_factor.account_id);
+        store.two_factor.push(two_factor.clone());
+        Ok(())
+    }
+
+    async fn use_step(&self, account_id: &str, step: i64) -> Result<bool, AccountError> {
+        let mut store = self.store.lock();
+        match store.two_factor.iter_mut().find(|two_factor| two_factor.account_id == account_id && two_factor.last_step < step) {
+            Some(two_factor) => {
+                two_factor.last_step = step;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn delete(&self, account_id: &str) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        store.two_factor.retain(|two_factor| two_factor.account_id != account_id);
+        store.recovery_codes.retain(|(owner, _)| owner != account_id);
+        Ok(())
+    }
+
+    async fn set_recovery_codes(&self, account_id: &str, hashes: &[String]) -> Result<(), AccountError> {
+        let mut store = self.store.lock();
+        store.recovery_codes.retain(|(owner, _)| owner != account_id);
+        store.recovery_codes.extend(hashes.iter().map(|hash| (account_id.to_string(), hash.clone())));
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, account_id: &str, hash: &str) -> Result<bool, AccountError> {
+        let mut store = self.store.lock();
+        match store.recovery_codes.iter().position(|(owner, code_hash)| owner == account_id && code_hash == hash) {
+            Some(index) => {
+                store.recovery_codes.remove(index);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+/// Links accounts to the subject of an OpenID Connect provider, see
+/// [`crate::account::oidc`].
+#[async_trait]
+pub trait ExternalIdentityRepository: Send + Sync {
+    async fn find(&self, issuer: &str, subject: &str) -> Result<Option<ExternalIdentity>, AccountError>;
+
+    /// False if the subject is linked already, to this or any other
+    /// account.
+    async fn link(&self, identity: &ExternalIdentity) -> Result<bool, AccountError>;
+}
+
+pub struct PgExternalIdentityRepository {
+    pool: Pool
+}
+
+impl PgExternalIdentityRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgExternalIdentityRepository { pool }
+    }
+}
+
+#[async_trait]
+impl ExternalIdentityRepository for PgExternalIdentityRepository {
+    async fn find(&self, issuer: &str, subject: &str) -> Result<Option<ExternalIdentity>, AccountError> {
+        match db::query(&self.pool, "select * from find_external_identity($1, $2)", &[&issuer, &subject]).await {
+            Ok(res) => Ok(res.first().map(ExternalIdentity::from)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+
+    async fn link(&self, identity: &ExternalIdentity) -> Result<bool, AccountError> {
+        let sql = "select link_external_identity($1, $2, $3, $4)";
+        let params: [&(dyn ToSql + Sync); 4] = [&identity.issuer, &identity.subject, &identity.account_id, &identity.linked_at];
+        match db::query(&self.pool, sql, &params).await {
+            Ok(res) => Ok(res.first().map(|row| row.get::<_, bool>(0)).unwrap_or(false)),
+            Err(er) => Err(AccountError::parse_db_error(&er, &Account::default())),
+        }
+    }
+}
+
+pub struct MemoryExternalIdentityRepository {
+    store: SharedStore
+}
+
+impl MemoryExternalIdentityRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryExternalIdentityRepository { store }
+    }
+}
+
+#[async_trait]
+impl ExternalIdentityRepository for MemoryExternalIdentityRepository {
+    async fn find(&self, issuer: &str, subject: &str) -> Result<Option<ExternalIdentity>, AccountError> {
+        let store = self.store.lock();
+        Ok(store.external_identities.iter().find(|identity| identity.issuer == issuer && identity.subject == subject).cloned())
+    }
+
+    async fn link(&self, identity: &ExternalIdentity) -> Result<bool, AccountError> {
+        let mut store = self.store.lock();
+        if !store.accounts.iter().any(|acc| acc.id == identity.account_id) {
+            return Err(AccountError::AccountNotFound(identity.account_id.clone()));
+        }
+        if store.external_identities.iter().any(|other| other.issuer == identity.issuer && other.subject == identity.subject) {
+            return Ok(false);
+        }
+        store.external_identities.push(identity.clone());
+        Ok(true)
+    }
+}
@@ -1,65 +1,443 @@
-use std::fmt::Debug;
-
-use deadpool_postgres::{Pool, Manager};
-use reqwest::StatusCode;
 use rocket::form::Form;
-use rocket::http::{CookieJar, Cookie, SameSite};
-use rocket::request::{FromRequest, self};
+use rocket::http::CookieJar;
 use rocket::response::Redirect;
 use rocket::{serde::json::Json, post, Route};
-use rocket::{routes, State, Request, get};
-use serde_json::{Value, json};
-use crate::session::config::Session;
-use crate::thread;
-use crate::thread::config::{self, Thread};
+use rocket::{routes, State, get, patch, delete};
 use rocket::http::Status;
 
-use super::config::{Account, AccountConfig, AccountLogin};
-use super::enums::LoginMethod;
-use super::error;
+use crate::config::AppConfig;
+use crate::error::ApiError;
+use crate::mail::{Mail, Mailer};
+use crate::repository::Repositories;
+use crate::session::{config::{Device, Session}, cookie::CookieConfig};
+use crate::token::config::{AccessToken, Scope};
+use crate::validate::{self, ValidationErrors};
+
+use super::config::{
+    Account, AccountConfig, AccountLogin, AccountProfile, AccountUnlock, AccountUpdate, AccountVerify,
+    MagicLinkLogin, MagicLinkRequest, PasswordChange, PasswordForgot, PasswordReset, TwoFactorLogin
+};
+use super::enums::Rank;
+use super::error::AccountError;
+use super::guard::{AuthedAccount, RequireRank, rank::{Admin, Owner}};
+use super::lockout::LoginLimiter;
+use super::oidc::{OidcClient, OidcFlow};
+use super::onetime::Purpose;
+use super::twofactor::{LoginOutcome, RecoveryCodes, TwoFactorConfirm, TwoFactorEnrollment, TwoFactorPassword};
 
 
+/// With `verify_email` on, new accounts start at [`Rank::None`] and get
+/// a confirmation link mailed.
 #[post("/account/new", data = "<_acc>")]
-pub async fn account_new(_acc: Json<Account>, pool: &State<Pool>) -> Value {
-    let account = Account::new(_acc.username(), _acc.password(), _acc.email());
-    let acc_cfg = AccountConfig::new(pool.inner());
-    match acc_cfg.create(account).await {
-        Ok(_) => {
-            return json!({"status" : "SUCCESS"})
-        },
-        Err(v) => {
-            return json!({"status" : "FAILED", "reason": v.to_string()})
+pub async fn account_new(_acc: Json<Account>, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<(Status, Json<AccountProfile>), ApiError> {
+    if !app.registration_open {
+        return Err(AccountError::RegistrationClosed.into());
+    }
+    let acc_cfg = AccountConfig::new(repos);
+    let mut account = Account::new(_acc.username(), _acc.password(), _acc.email(), &repos.hasher).await?;
+    if app.verify_email {
+        account.rank = Rank::None;
+    }
+    acc_cfg.create(account.clone()).await?;
+    if app.verify_email {
+        // the account exists either way, the link can be resent.
+        if let Err(er) = mail_verification(&acc_cfg, &account, app, mailer).await {
+            println!("[Account] Could not issue the confirmation link of {}: {}", account.id(), er);
         }
     }
+    Ok((Status::Created, Json(AccountProfile::from(account))))
+}
+
+/// Mails a fresh confirmation link, older links stop working.
+async fn mail_verification(cfg: &AccountConfig<'_>, acc: &Account, app: &AppConfig, mailer: &State<Box<dyn Mailer>>) -> Result<(), AccountError> {
+    let token = cfg.issue_token(acc, Purpose::EmailVerification).await?;
+    let mail = Mail {
+        to: acc.email().to_string(),
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nplease confirm your email address by opening\n\n{}/verify-email?token={}\n\nwithin the next day.",
+            acc.username(), app.frontend_origin.trim_end_matches('/'), token
+        )
+    };
+    if let Err(er) = mailer.send(mail).await {
+        println!("[Account] Could not mail the confirmation link of {}: {}", acc.id(), er);
+    }
+    Ok(())
+}
+
+/// Redeems a mailed confirmation token, the email counts as confirmed
+/// and a None account becomes a Member. Works without being logged in,
+/// the link may be opened anywhere.
+#[post("/account/verify", format = "json", data = "<verify>")]
+pub async fn account_verify(verify: Json<AccountVerify>, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let (one_time, acc) = cfg.find_token(&verify.token, Purpose::EmailVerification).await?;
+    cfg.consume_token(&one_time).await?;
+    Ok(Json(AccountProfile::from(cfg.confirm_email(&acc).await?)))
+}
+
+/// Mails another confirmation link, at most once per
+/// [`OneTimeToken::RESEND_INTERVAL`](super::onetime::OneTimeToken::RESEND_INTERVAL).
+#[post("/account/verify/resend")]
+pub async fn account_verify_resend(authed: AuthedAccount, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    authed.require_scope(Scope::AccountWrite)?;
+    if authed.account.email_confirmed() {
+        return Err(AccountError::AlreadyVerified.into());
+    }
+    let cfg = AccountConfig::new(repos);
+    cfg.throttle_token(&authed.account, Purpose::EmailVerification).await?;
+    mail_verification(&cfg, &authed.account, app, mailer).await?;
+    Ok(Status::Accepted)
 }
 
 #[post("/account/login", data = "<login>")]
-pub async fn account_login(jar: &CookieJar<'_>, login: Form<AccountLogin>, pool: &State<Pool>) -> Result<Redirect, Status> {
-    let cfg = AccountConfig::new(pool.inner());
-    let email = &login.email;
-    let password = &login.password;
-    let is_auth = cfg.auth(LoginMethod::Email, &email, password).await;
-    match is_auth {
-        Ok(res) => {
-            res.save(cfg).await;
-            let sid = Cookie::new("sid", res.session_id);
-            jar.add(sid);
-            return Ok(
-                Redirect::to("http://127.0.0.1:5173")
-            );
-        },
-        Err(_) => {
-            return Err(Status::NotFound);
-        },
-    }
-}
-
-#[get("/account/logout")]
-pub async fn account_logout(jar: &CookieJar<'_>) -> Redirect {
-    jar.remove(Cookie::named("sid"));
-    Redirect::to("http://127.0.0.1:5173")
+pub async fn account_login(jar: &CookieJar<'_>, login: Form<AccountLogin>, device: Device, limiter: LoginLimiter<'_>, cookies: &State<CookieConfig>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let outcome = cfg.auth(login.method(), &login.identifier, &login.password, &limiter).await?;
+    login_redirect(cfg, outcome, jar, device, cookies, app).await
+}
+
+/// Mails a login link if the email belongs to an account. Always
+/// answers with 202 so nobody can find out which emails are in use,
+/// only an ip asking too often gets a 429.
+#[post("/account/login/link", format = "json", data = "<request>")]
+pub async fn account_login_link(request: Json<MagicLinkRequest>, limiter: LoginLimiter<'_>, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    limiter.reserve_mail(&cfg).await?;
+    let acc = match cfg.find_by_email(&request.email).await? {
+        Some(acc) if !acc.is_banned() => acc,
+        _ => return Ok(Status::Accepted),
+    };
+    // answering with a 429 would tell the email is in use.
+    if let Err(AccountError::TooManyRequests(_)) = cfg.throttle_token(&acc, Purpose::MagicLink).await {
+        return Ok(Status::Accepted);
+    }
+    let token = cfg.issue_token(&acc, Purpose::MagicLink).await?;
+    let mail = Mail {
+        to: acc.email().to_string(),
+        subject: "Your login link".to_string(),
+        body: format!(
+            "Hi {},\n\nopen\n\n{}/login/link?token={}\n\nwithin the next 15 minutes to log in. The link works once. If you didn't ask for it you can ignore this mail.",
+            acc.username(), app.frontend_origin.trim_end_matches('/'), token
+        )
+    };
+    if let Err(er) = mailer.send(mail).await {
+        println!("[Account] Could not mail the login link of {}: {}", acc.id(), er);
+    }
+    Ok(Status::Accepted)
+}
+
+/// Redeems a mailed login link, the frontend posts the token here
+/// (mail scanners opening links would use it up otherwise).
+#[post("/account/login/link/redeem", data = "<login>")]
+pub async fn account_login_link_redeem(jar: &CookieJar<'_>, login: Form<MagicLinkLogin>, device: Device, limiter: LoginLimiter<'_>, cookies: &State<CookieConfig>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let outcome = cfg.auth_link(&login.token, &limiter).await?;
+    login_redirect(cfg, outcome, jar, device, cookies, app).await
+}
+
+/// Sends the browser to the OpenID Connect provider of `[app.oidc]`,
+/// which sends it back to [`account_oidc_callback`].
+#[get("/account/oidc/login")]
+pub async fn account_oidc_login(jar: &CookieJar<'_>, oidc: &State<OidcClient>, cookies: &State<CookieConfig>) -> Result<Redirect, ApiError> {
+    let flow = OidcFlow::new();
+    let url = oidc.authorize_url(&flow).await?;
+    flow.add(jar, cookies);
+    Ok(Redirect::to(url))
+}
+
+/// Finishes a login with the provider, the first one links the account
+/// with the same confirmed email or creates a new one. Ends up just like
+/// the password login.
+#[get("/account/oidc/callback?<code>&<state>&<error>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn account_oidc_callback(code: Option<&str>, state: Option<&str>, error: Option<&str>, jar: &CookieJar<'_>, device: Device, limiter: LoginLimiter<'_>, oidc: &State<OidcClient>, cookies: &State<CookieConfig>, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+    oidc.check_enabled()?;
+    // the state ties the callback to the browser that started the login.
+    let flow = OidcFlow::take(jar)
+        .filter(|flow| state == Some(flow.state.as_str()))
+        .ok_or_else(|| AccountError::OidcInvalid("the login expired or was started elsewhere".to_string()))?;
+    if let Some(error) = error {
+        return Err(AccountError::OidcInvalid(format!("the provider answered {}", error)).into());
+    }
+    let code = code.ok_or_else(|| AccountError::OidcInvalid("the provider sent no code".to_string()))?;
+    let claims = oidc.exchange(code, &flow).await?;
+
+    let cfg = AccountConfig::new(repos);
+    let (acc, created) = cfg.oidc_account(&claims, app).await?;
+    if created && *acc.rank() == Rank::None {
+        if let Err(er) = mail_verification(&cfg, &acc, app, mailer).await {
+            println!("[Account] Could not issue the confirmation link of {}: {}", acc.id(), er);
+        }
+    }
+    let outcome = cfg.auth_oidc(&acc, &limiter).await?;
+    login_redirect(cfg, outcome, jar, device, cookies, app).await
+}
+
+/// Sets the cookie of a successful login, or sends accounts with 2FA to
+/// the frontend to ask for their code.
+async fn login_redirect(cfg: AccountConfig<'_>, outcome: LoginOutcome, jar: &CookieJar<'_>, device: Device, cookies: &CookieConfig, app: &AppConfig) -> Result<Redirect, ApiError> {
+    match outcome {
+        LoginOutcome::Session(res) => login_cookie(cfg, *res, jar, device, cookies, app).await,
+        // the frontend asks for the code and posts it to /account/login/two-factor.
+        LoginOutcome::TwoFactor(pending) => Ok(Redirect::to(format!(
+            "{}/login/two-factor?pending_token={}",
+            app.frontend_origin.trim_end_matches('/'), pending.pending_token
+        ))),
+    }
+}
+
+/// The second step of the cookie login for accounts with 2FA.
+#[post("/account/login/two-factor", data = "<login>")]
+pub async fn account_login_two_factor(jar: &CookieJar<'_>, login: Form<TwoFactorLogin>, device: Device, limiter: LoginLimiter<'_>, cookies: &State<CookieConfig>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let res = cfg.auth_two_factor(&login.pending_token, &login.code, &limiter).await?;
+    login_cookie(cfg, res, jar, device, cookies, app).await
+}
+
+/// Stores a successful login and hands out its cookie.
+async fn login_cookie(cfg: AccountConfig<'_>, mut res: Session, jar: &CookieJar<'_>, device: Device, cookies: &CookieConfig, app: &AppConfig) -> Result<Redirect, ApiError> {
+    res.set_device(device);
+    res.set_lifetime(app.session_lifetime_ms());
+    res.save(cfg).await?;
+    cookies.add(jar, &res);
+    Ok(
+        Redirect::to(app.frontend_origin.clone())
+    )
+}
+
+/// Ends the session on the server as well, a copied cookie is
+/// useless afterwards. A POST with the csrf token, so a link or image on
+/// another site can't log anyone out.
+#[post("/account/logout")]
+pub async fn account_logout(authed: Option<AuthedAccount>, jar: &CookieJar<'_>, cookies: &State<CookieConfig>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Redirect, ApiError> {
+    if let Some(authed) = authed {
+        let cfg = AccountConfig::new(repos);
+        if let Ok(session) = authed.session() {
+            session.revoke(&cfg).await?;
+        }
+    }
+    cookies.remove(jar);
+    Ok(Redirect::to(app.frontend_origin.clone()))
+}
+
+#[get("/account/me")]
+pub async fn account_me(authed: AuthedAccount) -> Result<Json<Account>, ApiError> {
+    authed.require_scope(Scope::AccountRead)?;
+    Ok(Json(authed.account))
+}
+
+/// Changing the email takes the current password (counted like a
+/// login). With `verify_email` on the new email has to be confirmed,
+/// the account is [`Rank::None`] until then and gets a link mailed.
+#[patch("/account/me", format = "json", data = "<update>")]
+pub async fn account_update(authed: AuthedAccount, update: Json<AccountUpdate>, limiter: LoginLimiter<'_>, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<Json<Account>, ApiError> {
+    authed.require_scope(Scope::AccountWrite)?;
+    update.validate()?;
+    let cfg = AccountConfig::new(repos);
+    let changes_email = update.changes_email(&authed.account);
+    if changes_email {
+        let password = update.current_password.as_deref().ok_or_else(|| ApiError::Validation {
+            field: "current_password",
+            message: "Changing the email takes the current password.".to_string()
+        })?;
+        cfg.check_password(&authed.account, password, &limiter).await?;
+    }
+    let acc = cfg.update(&authed.account, &update, app).await?;
+    if changes_email && app.verify_email {
+        if let Err(er) = mail_verification(&cfg, &acc, app, mailer).await {
+            println!("[Account] Could not issue the confirmation link of {}: {}", acc.id(), er);
+        }
+    }
+    Ok(Json(acc))
+}
+
+#[delete("/account/me")]
+pub async fn account_delete(authed: AuthedAccount, jar: &CookieJar<'_>, cookies: &State<CookieConfig>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    // deleting the account takes a real login, never just a token.
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    cfg.delete(&authed.account).await?;
+    cookies.remove(jar);
+    Ok(Status::NoContent)
+}
+
+/// Takes the current password as well, a stolen session alone can't
+/// lock the owner out. Wrong ones count like failed logins, every other
+/// session and every access token is ended.
+#[post("/account/password", format = "json", data = "<change>")]
+pub async fn account_password(authed: AuthedAccount, change: Json<PasswordChange>, limiter: LoginLimiter<'_>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let session = authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    cfg.check_password(&authed.account, &change.current_password, &limiter).await?;
+    let mut errors = ValidationErrors::default();
+    errors.check(validate::password(&change.new_password, authed.account.username()));
+    errors.finish()?;
+    cfg.change_password(&authed.account, &change.new_password).await?;
+    session.remove_others(&cfg).await?;
+    AccessToken::remove_all(&cfg, authed.account.id()).await?;
+    Ok(Status::NoContent)
+}
+
+/// Starts enrolling in 2FA, the secret only has to be entered into an
+/// authenticator app (or scanned from `uri`) and confirmed. Takes the
+/// current password (counted like a login), like every other 2FA change.
+#[post("/account/two-factor", format = "json", data = "<enroll>")]
+pub async fn account_two_factor(authed: AuthedAccount, enroll: Json<TwoFactorPassword>, limiter: LoginLimiter<'_>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Json<TwoFactorEnrollment>, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    cfg.check_password(&authed.account, &enroll.password, &limiter).await?;
+    let two_factor = cfg.enroll_two_factor(&authed.account).await?;
+    Ok(Json(TwoFactorEnrollment {
+        uri: two_factor.uri(&app.two_factor.issuer, authed.account.username()),
+        secret: two_factor.secret
+    }))
+}
+
+/// Enables 2FA with a first code and the current password, answers with
+/// the recovery codes.
+#[post("/account/two-factor/confirm", format = "json", data = "<confirm>")]
+pub async fn account_two_factor_confirm(authed: AuthedAccount, confirm: Json<TwoFactorConfirm>, limiter: LoginLimiter<'_>, repos: &State<Repositories>) -> Result<Json<RecoveryCodes>, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    cfg.check_password(&authed.account, &confirm.password, &limiter).await?;
+    let recovery_codes = cfg.confirm_two_factor(&authed.account, &confirm.code).await?;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Takes the current password (counted like a login), like changing it
+/// does.
+#[post("/account/two-factor/disable", format = "json", data = "<disable>")]
+pub async fn account_two_factor_disable(authed: AuthedAccount, disable: Json<TwoFactorPassword>, limiter: LoginLimiter<'_>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    cfg.check_password(&authed.account, &disable.password, &limiter).await?;
+    cfg.disable_two_factor(&authed.account).await?;
+    Ok(Status::NoContent)
+}
+
+/// Mails a reset link if the email belongs to an account. Always
+/// answers with 202 so nobody can find out which emails are in use,
+/// only an ip asking too often gets a 429.
+#[post("/account/password/forgot", format = "json", data = "<forgot>")]
+pub async fn account_password_forgot(forgot: Json<PasswordForgot>, limiter: LoginLimiter<'_>, app: &State<AppConfig>, mailer: &State<Box<dyn Mailer>>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    limiter.reserve_mail(&cfg).await?;
+    let acc = match cfg.find_by_email(&forgot.email).await? {
+        Some(acc) => acc,
+        None => return Ok(Status::Accepted),
+    };
+    // answering with a 429 would tell the email is in use.
+    if let Err(AccountError::TooManyRequests(_)) = cfg.throttle_token(&acc, Purpose::PasswordReset).await {
+        return Ok(Status::Accepted);
+    }
+    let token = cfg.issue_token(&acc, Purpose::PasswordReset).await?;
+    let mail = Mail {
+        to: acc.email().to_string(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nsomeone asked to reset the password of your account. If that was you, open\n\n{}/reset-password?token={}\n\nwithin the next hour. Otherwise you can ignore this mail.",
+            acc.username(), app.frontend_origin.trim_end_matches('/'), token
+        )
+    };
+    if let Err(er) = mailer.send(mail).await {
+        println!("[Account] Could not mail the reset link of {}: {}", acc.id(), er);
+    }
+    Ok(Status::Accepted)
+}
+
+/// Redeems a mailed reset token. The token only works once, every
+/// session and access token of the account is ended and a lockout is
+/// lifted.
+#[post("/account/password/reset", format = "json", data = "<reset>")]
+pub async fn account_password_reset(reset: Json<PasswordReset>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let (one_time, acc) = cfg.find_token(&reset.token, Purpose::PasswordReset).await?;
+    let mut errors = ValidationErrors::default();
+    errors.check(validate::password(&reset.password, acc.username()));
+    errors.finish()?;
+    cfg.consume_token(&one_time).await?;
+    cfg.change_password(&acc, &reset.password).await?;
+    cfg.unlock(&acc).await?;
+    Session::remove_all(&cfg, acc.id()).await?;
+    AccessToken::remove_all(&cfg, acc.id()).await?;
+    Ok(Status::NoContent)
+}
+
+/// Redeems the unlock link mailed when an account got locked by failed
+/// logins.
+#[post("/account/unlock", format = "json", data = "<unlock>")]
+pub async fn account_unlock(unlock: Json<AccountUnlock>, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let (one_time, acc) = cfg.find_token(&unlock.token, Purpose::Unlock).await?;
+    cfg.consume_token(&one_time).await?;
+    cfg.unlock(&acc).await?;
+    println!("[Account] {} was unlocked", acc.id());
+    Ok(Status::NoContent)
+}
+
+#[get("/account/<username>")]
+pub async fn account_profile(username: &str, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    Ok(Json(AccountProfile::from(cfg.find("username", username).await?)))
+}
+
+/// Finds the account `username` points to, as long as it ranks below
+/// the caller. Nobody can ban or (de)rank themselves or their equals.
+async fn find_subordinate(cfg: &AccountConfig<'_>, authed: &AuthedAccount, username: &str) -> Result<Account, ApiError> {
+    authed.require_scope(Scope::AccountWrite)?;
+    let target = cfg.find("username", username).await?;
+    if target.rank() >= authed.account.rank() {
+        return Err(AccountError::NotSubordinate(target.username().to_string()).into());
+    }
+    Ok(target)
+}
+
+#[post("/account/<username>/ban")]
+pub async fn account_ban(username: &str, authed: RequireRank<Admin>, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let target = find_subordinate(&cfg, &authed, username).await?;
+    Ok(Json(AccountProfile::from(cfg.set_banned(&target, true).await?)))
+}
+
+#[post("/account/<username>/unban")]
+pub async fn account_unban(username: &str, authed: RequireRank<Admin>, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let target = find_subordinate(&cfg, &authed, username).await?;
+    Ok(Json(AccountProfile::from(cfg.set_banned(&target, false).await?)))
+}
+
+#[post("/account/<username>/promote")]
+pub async fn account_promote(username: &str, authed: RequireRank<Owner>, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let target = find_subordinate(&cfg, &authed, username).await?;
+    let rank = target.rank().promoted().ok_or_else(|| ApiError::Validation {
+        field: "rank",
+        message: format!("{} already has the highest rank.", target.username())
+    })?;
+    Ok(Json(AccountProfile::from(cfg.set_rank(&target, rank).await?)))
+}
+
+#[post("/account/<username>/demote")]
+pub async fn account_demote(username: &str, authed: RequireRank<Owner>, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let target = find_subordinate(&cfg, &authed, username).await?;
+    let rank = target.rank().demoted().ok_or_else(|| ApiError::Validation {
+        field: "rank",
+        message: format!("{} already has the lowest rank.", target.username())
+    })?;
+    Ok(Json(AccountProfile::from(cfg.set_rank(&target, rank).await?)))
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![account_new, account_login, account_logout]
+    routes![
+        account_new, account_login, account_login_two_factor, account_login_link, account_login_link_redeem,
+        account_oidc_login, account_oidc_callback,
+        account_logout,
+        account_me, account_update, account_delete, account_profile,
+        account_verify, account_verify_resend, account_unlock,
+        account_password, account_password_forgot, account_password_reset,
+        account_two_factor, account_two_factor_confirm, account_two_factor_disable,
+        account_ban, account_unban, account_promote, account_demote
+    ]
 }
\ No newline at end of file
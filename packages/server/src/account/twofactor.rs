@@ -0,0 +1,176 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use nanoid::nanoid;
+use rand_core::{OsRng, RngCore};
+use rocket::serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use tokio_postgres::Row;
+
+use crate::session::config::Session;
+
+/// The time-based one-time password (RFC 6238) of an account, the codes
+/// authenticator apps show. Enrolling stores the secret disabled, it's
+/// only asked for on login once a first code confirmed it.
+#[derive(Debug, Clone)]
+pub struct TwoFactor {
+    pub account_id: String,
+    /// Base32 without padding, like authenticator apps expect it.
+    pub secret: String,
+    pub enabled: bool,
+    /// The last time step a code was accepted for, a code never works twice.
+    pub last_step: i64
+}
+
+impl TwoFactor {
+    /// Seconds a code is valid for.
+    pub const STEP: u64 = 30;
+    /// Digits of a code.
+    pub const DIGITS: u32 = 6;
+    /// Recovery codes handed out when 2FA is confirmed.
+    pub const RECOVERY_CODES: usize = 10;
+
+    /// A new, not yet enabled secret for the account.
+    pub fn new(account_id: &str) -> Self {
+        let mut secret = [0u8; 20];
+        OsRng.fill_bytes(&mut secret);
+        TwoFactor {
+            account_id: account_id.to_string(),
+            secret: BASE32_NOPAD.encode(&secret),
+            enabled: false,
+            last_step: 0
+        }
+    }
+
+    /// The `otpauth://` uri authenticator apps scan (as a qr code).
+    pub fn uri(&self, issuer: &str, username: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{username}?secret={}&issuer={issuer}&algorithm=SHA1&digits={}&period={}",
+            self.secret, TwoFactor::DIGITS, TwoFactor::STEP,
+            issuer = TwoFactor::escape(issuer), username = TwoFactor::escape(username)
+        )
+    }
+
+    /// The time step `code` belongs to, one step of clock drift either way
+    /// is allowed. Steps at or before [`TwoFactor::last_step`] never match.
+    pub fn verify(&self, code: &str) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != TwoFactor::DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let key = BASE32_NOPAD.decode(self.secret.as_bytes()).ok()?;
+        let now = (TwoFactor::now() / TwoFactor::STEP) as i64;
+        (now - 1..=now + 1)
+            .filter(|step| *step > self.last_step)
+            .find(|step| TwoFactor::code(&key, *step as u64) == code)
+    }
+
+    /// The code of a time step, HOTP (RFC 4226) with HMAC-SHA1.
+    fn code(key: &[u8], step: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac takes keys of any length");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+        format!("{:0width$}", binary % 10u32.pow(TwoFactor::DIGITS), width = TwoFactor::DIGITS as usize)
+    }
+
+    /// The code an authenticator app shows for `secret` right now.
+    #[cfg(test)]
+    pub fn current_code(secret: &str) -> String {
+        let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        TwoFactor::code(&key, TwoFactor::now() / TwoFactor::STEP)
+    }
+
+    /// Fresh recovery codes, plain for the user and hashed for the store.
+    /// Each one can be used instead of a code once.
+    pub fn recovery_codes() -> (Vec<String>, Vec<String>) {
+        let alphabet: Vec<char> = "abcdefghijkmnpqrstuvwxyz23456789".chars().collect();
+        let codes: Vec<String> = (0..TwoFactor::RECOVERY_CODES)
+            .map(|_| format!("{}-{}", nanoid!(5, &alphabet), nanoid!(5, &alphabet)))
+            .collect();
+        let hashes = codes.iter().map(|code| TwoFactor::hash_recovery_code(code)).collect();
+        (codes, hashes)
+    }
+
+    /// Recovery codes are compared case insensitive and without spaces.
+    pub fn hash_recovery_code(code: &str) -> String {
+        Session::hash(&code.trim().to_lowercase().replace(' ', ""))
+    }
+
+    fn escape(value: &str) -> String {
+        value.bytes().map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        }).collect()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+}
+
+impl From<&Row> for TwoFactor {
+    fn from(value: &Row) -> Self {
+        TwoFactor {
+            account_id: value.get(0),
+            secret: value.get(1),
+            enabled: value.get(2),
+            last_step: value.get(3)
+        }
+    }
+}
+
+/// What a correct password gets you, accounts with 2FA only get a
+/// session once [`AccountConfig::auth_two_factor`](super::config::AccountConfig::auth_two_factor)
+/// checked their code.
+pub enum LoginOutcome {
+    Session(Box<Session>),
+    TwoFactor(PendingLogin)
+}
+
+/// The first step of a login with 2FA, `pending_token` is sent back
+/// together with the code.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PendingLogin {
+    pub pending_token: String,
+    /// Seconds the token stays valid.
+    pub expires_in: u128
+}
+
+/// What `POST /api/account/two-factor` answers with, the secret is
+/// shown this once.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    pub uri: String
+}
+
+/// What confirming 2FA answers with, the codes are shown this once.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>
+}
+
+/// The body of `POST /api/account/two-factor/confirm`, the first code
+/// from the authenticator app and the current password.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorConfirm {
+    pub code: String,
+    // a missing password is just a wrong one.
+    #[serde(default)]
+    pub password: String
+}
+
+/// The body of `POST /api/account/two-factor` and `/disable`.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorPassword {
+    #[serde(default)]
+    pub password: String
+}
@@ -0,0 +1,516 @@
+use std::{fmt, net::IpAddr, sync::Arc, time::Duration};
+
+use deadpool_postgres::{Config, ManagerConfig, Pool, PoolConfig, RecyclingMethod, Runtime, SslMode, Timeouts};
+use native_tls::TlsConnector;
+use postgres_native_tls::MakeTlsConnector;
+use rocket::{
+    figment::{providers::Env, Figment},
+    http::uri::Absolute,
+    serde::Deserialize,
+    Request
+};
+
+use crate::{
+    account::hasher::{Argon2idHasher, PasswordHasher, Pbkdf2Hasher},
+    mail::{FileMailer, LogMailer, Mailer}
+};
+
+/// The `[app]` table of Rocket.toml.
+///
+/// Every key can be overridden with an `APP_` env var (e.g.
+/// `APP_REGISTRATION_OPEN=false`), the `[app.database]` keys with a
+/// `PG_` one (e.g. `PG_HOST`, `PG_PASS`). A `.env` file is read too.
+///
+/// ```toml
+/// [default.app]
+/// backend = "postgres"
+/// frontend_origin = "http://127.0.0.1:5173"
+/// session_lifetime = 604800
+/// registration_open = true
+/// verify_email = true
+/// trust_ip_header = false
+///
+/// [default.app.database]
+/// host = "localhost"
+/// port = 5432
+/// tls = "disable"
+///
+/// [default.app.password]
+/// algorithm = "argon2id"
+///
+/// [default.app.mail]
+/// transport = "log"
+///
+/// [default.app.login]
+/// free_attempts = 5
+///
+/// [default.app.two_factor]
+/// issuer = "blog"
+///
+/// [default.app.oidc]
+/// enabled = false
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct AppConfig {
+    /// Where accounts, sessions and threads are kept.
+    pub backend: Backend,
+    /// Where the frontend lives, login and logout redirect there.
+    pub frontend_origin: String,
+    /// How long a login stays valid, in seconds.
+    pub session_lifetime: u64,
+    /// Whether `POST /api/account/new` is open to everyone.
+    pub registration_open: bool,
+    /// Whether new accounts start at `Rank::None` until they confirm
+    /// their email, unconfirmed accounts can't post threads.
+    pub verify_email: bool,
+    /// Whether the ip in rocket's `ip_header` (e.g. `X-Real-IP`) is
+    /// believed, see [`AppConfig::client_ip`]. Only turn it on behind a
+    /// proxy that always sets the header.
+    pub trust_ip_header: bool,
+    pub database: DatabaseConfig,
+    pub password: PasswordConfig,
+    pub mail: MailConfig,
+    pub login: LoginConfig,
+    pub two_factor: TwoFactorConfig,
+    pub oidc: OidcConfig
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Postgres,
+            frontend_origin: "http://127.0.0.1:5173".to_string(),
+            session_lifetime: 604800,
+            registration_open: true,
+            verify_email: true,
+            trust_ip_header: false,
+            database: DatabaseConfig::default(),
+            password: PasswordConfig::default(),
+            mail: MailConfig::default(),
+            login: LoginConfig::default(),
+            two_factor: TwoFactorConfig::default(),
+            oidc: OidcConfig::default()
+        }
+    }
+}
+
+/// See [`crate::repository::Repositories`].
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Backend {
+    Postgres,
+    /// Keeps everything in the process and forgets it on restart, no
+    /// database needed. For local development and tests.
+    Memory
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TlsMode {
+    Disable,
+    Prefer,
+    Require
+}
+
+impl From<TlsMode> for SslMode {
+    fn from(value: TlsMode) -> Self {
+        match value {
+            TlsMode::Disable => SslMode::Disable,
+            TlsMode::Prefer => SslMode::Prefer,
+            TlsMode::Require => SslMode::Require,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct DatabaseConfig {
+    pub host: String,
+    pub port: u16,
+    pub dbname: String,
+    pub user: String,
+    #[serde(alias = "pass")]
+    pub password: Option<String>,
+    pub tls: TlsMode,
+    pub pool_size: usize,
+    /// How long a request waits for a connection before it's answered
+    /// with a 503, in ms.
+    pub pool_timeout_ms: u64,
+    /// Set to false when migrations are run with `server migrate`.
+    pub migrate_on_start: bool
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 5432,
+            dbname: "blog".to_string(),
+            user: "postgres".to_string(),
+            password: None,
+            tls: TlsMode::Disable,
+            pool_size: 16,
+            pool_timeout_ms: 5000,
+            migrate_on_start: true
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Argon2id,
+    Pbkdf2
+}
+
+/// The `[app.password]` table, how new passwords are hashed. Stored
+/// hashes made with another algorithm or other costs are replaced on
+/// the next login.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PasswordConfig {
+    pub algorithm: HashAlgorithm,
+    /// Argon2id memory cost, in KiB.
+    pub memory_kib: u32,
+    /// Argon2id passes over the memory.
+    pub iterations: u32,
+    /// Argon2id lanes.
+    pub parallelism: u32,
+    /// Only used with `algorithm = "pbkdf2"`.
+    pub pbkdf2_rounds: u32
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        // the OWASP recommendations.
+        Self {
+            algorithm: HashAlgorithm::Argon2id,
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+            pbkdf2_rounds: 600000
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Prints mails to stdout.
+    Log,
+    /// Writes mails into `outbox` as .eml files.
+    File
+}
+
+/// The `[app.mail]` table, see [`crate::mail::Mailer`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    pub from: String,
+    pub outbox: String
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::Log,
+            from: "blog@localhost".to_string(),
+            outbox: "outbox".to_string()
+        }
+    }
+}
+
+/// The `[app.login]` table, how failed logins slow down further guesses
+/// (see [`crate::account::lockout`]). Accounts and ips are counted on
+/// their own.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct LoginConfig {
+    /// Failed logins on an account before it has to wait.
+    pub free_attempts: u32,
+    /// Failed logins from an ip before it has to wait.
+    pub ip_free_attempts: u32,
+    /// Reset and login link mails an ip can ask for before it has to
+    /// wait, whether the email is in use or not.
+    pub ip_free_mails: u32,
+    /// The first wait in seconds, doubled with every further failure.
+    pub base_delay: u64,
+    /// The longest wait in seconds, an account reaching it is locked
+    /// and its owner gets a mail with an unlock link.
+    pub max_delay: u64,
+    /// Failures are forgotten once there was none for this long, in seconds.
+    pub reset_after: u64
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            free_attempts: 5,
+            ip_free_attempts: 20,
+            ip_free_mails: 10,
+            base_delay: 1,
+            max_delay: 900,
+            reset_after: 86400
+        }
+    }
+}
+
+/// The `[app.two_factor]` table, see [`crate::account::twofactor`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct TwoFactorConfig {
+    /// The name authenticator apps list the account under.
+    pub issuer: String
+}
+
+impl Default for TwoFactorConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "blog".to_string()
+        }
+    }
+}
+
+/// The `[app.oidc]` table, signing in with an OpenID Connect provider
+/// (see [`crate::account::oidc`]). Off unless `enabled`, e.g.
+/// `APP_OIDC='{enabled=true,issuer="https://accounts.google.com",client_id="..",client_secret=".."}'`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct OidcConfig {
+    pub enabled: bool,
+    /// The provider's issuer, `/.well-known/openid-configuration` is
+    /// read from there.
+    pub issuer: String,
+    pub client_id: String,
+    /// Left out for public clients, PKCE is used either way.
+    pub client_secret: Option<String>,
+    /// Where the provider sends the browser back to, the
+    /// `/api/account/oidc/callback` of this server.
+    pub redirect_uri: String,
+    /// Space separated, `openid` and `email` are needed to link accounts.
+    pub scopes: String
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: None,
+            redirect_uri: "http://127.0.0.1:8000/api/account/oidc/callback".to_string(),
+            scopes: "openid email profile".to_string()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A key has the wrong type, e.g. `PG_PORT=abc`.
+    Extract(String),
+    Invalid { key: &'static str, message: String },
+    Pool(String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Extract(er) => write!(
+                f,
+                "{}",
+                er
+            ),
+            ConfigError::Invalid { key, message } => write!(
+                f,
+                "app.{}: {}",
+                key,
+                message
+            ),
+            ConfigError::Pool(message) => write!(
+                f,
+                "Could not set up the database pool: {}",
+                message
+            ),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Rocket's own figment (Rocket.toml and `ROCKET_` env vars) plus the
+    /// `APP_` and `PG_` env vars. Rocket is built from the same figment.
+    pub fn figment() -> Figment {
+        dotenv::dotenv().ok();
+        rocket::Config::figment()
+            .merge(Env::prefixed("APP_").map(|key| format!("app.{}", key).into()).global())
+            .merge(Env::prefixed("PG_").map(|key| format!("app.database.{}", key).into()).global())
+    }
+
+    /// Reads the `[app]` table and checks it, all problems are returned
+    /// at once.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let figment = AppConfig::figment();
+    /// let config = AppConfig::from_figment(&figment)?;
+    /// ```
+    pub fn from_figment(figment: &Figment) -> Result<AppConfig, Vec<ConfigError>> {
+        let config = match figment.extract_inner::<AppConfig>("app") {
+            Ok(config) => config,
+            Err(er) if er.missing() => AppConfig::default(),
+            Err(er) => return Err(er.into_iter().map(|er| ConfigError::Extract(er.to_string())).collect()),
+        };
+        let errors = config.validate();
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let mut invalid = |key: &'static str, message: &str| errors.push(ConfigError::Invalid { key, message: message.to_string() });
+
+        match Absolute::parse(&self.frontend_origin) {
+            Ok(origin) if matches!(origin.scheme(), "http" | "https") => (),
+            _ => invalid("frontend_origin", "must be an absolute http(s) url"),
+        }
+        if self.session_lifetime < 60 {
+            invalid("session_lifetime", "must be at least 60 seconds");
+        }
+        if self.database.host.is_empty() {
+            invalid("database.host", "can't be empty");
+        }
+        if self.database.dbname.is_empty() {
+            invalid("database.dbname", "can't be empty");
+        }
+        if self.database.user.is_empty() {
+            invalid("database.user", "can't be empty");
+        }
+        if self.database.port == 0 {
+            invalid("database.port", "can't be 0");
+        }
+        if self.database.pool_size == 0 {
+            invalid("database.pool_size", "must be at least 1");
+        }
+        if self.database.pool_timeout_ms == 0 {
+            invalid("database.pool_timeout_ms", "must be at least 1");
+        }
+        if let Err(er) = self.password.argon2_params() {
+            invalid("password", &er.to_string());
+        }
+        if self.password.pbkdf2_rounds < 10000 {
+            invalid("password.pbkdf2_rounds", "must be at least 10000");
+        }
+        if self.mail.from.is_empty() {
+            invalid("mail.from", "can't be empty");
+        }
+        if self.mail.transport == MailTransport::File && self.mail.outbox.is_empty() {
+            invalid("mail.outbox", "can't be empty");
+        }
+        if self.login.free_attempts == 0 || self.login.ip_free_attempts == 0 || self.login.ip_free_mails == 0 {
+            invalid("login", "free_attempts, ip_free_attempts and ip_free_mails must be at least 1");
+        }
+        if self.login.base_delay == 0 {
+            invalid("login.base_delay", "must be at least 1");
+        }
+        if self.login.max_delay < self.login.base_delay {
+            invalid("login.max_delay", "can't be lower than base_delay");
+        }
+        if self.login.reset_after < self.login.max_delay {
+            invalid("login.reset_after", "can't be lower than max_delay");
+        }
+        if self.two_factor.issuer.is_empty() {
+            invalid("two_factor.issuer", "can't be empty");
+        }
+        if self.oidc.enabled {
+            for (key, url) in [("oidc.issuer", &self.oidc.issuer), ("oidc.redirect_uri", &self.oidc.redirect_uri)] {
+                match Absolute::parse(url) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") => (),
+                    _ => invalid(key, "must be an absolute http(s) url"),
+                }
+            }
+            if self.oidc.client_id.is_empty() {
+                invalid("oidc.client_id", "can't be empty");
+            }
+            if !self.oidc.scopes.split_whitespace().any(|scope| scope == "openid") {
+                invalid("oidc.scopes", "must contain openid");
+            }
+        }
+        errors
+    }
+
+    /// The ip a request comes from, what failed logins are counted
+    /// against and sessions show. Clients can send any `ip_header` they
+    /// like, so it's only used with [`AppConfig::trust_ip_header`] on,
+    /// otherwise the address of the connection is.
+    pub fn client_ip(&self, req: &Request<'_>) -> Option<IpAddr> {
+        if self.trust_ip_header {
+            req.client_ip()
+        } else {
+            req.remote().map(|remote| remote.ip())
+        }
+    }
+
+    /// [`AppConfig::session_lifetime`] in ms, like every other timestamp.
+    pub fn session_lifetime_ms(&self) -> u128 {
+        self.session_lifetime as u128 * 1000
+    }
+}
+
+impl DatabaseConfig {
+    /// Creates the pool, connections are only opened once they're needed.
+    pub fn pool(&self) -> Result<Pool, ConfigError> {
+        let timeout = Duration::from_millis(self.pool_timeout_ms);
+        let mut pg_cfg = Config::new();
+        pg_cfg.host = Some(self.host.clone());
+        pg_cfg.port = Some(self.port);
+        pg_cfg.dbname = Some(self.dbname.clone());
+        pg_cfg.user = Some(self.user.clone());
+        pg_cfg.password = self.password.clone();
+        pg_cfg.ssl_mode = Some(self.tls.into());
+        pg_cfg.connect_timeout = Some(timeout);
+        pg_cfg.manager = Some(ManagerConfig { recycling_method: RecyclingMethod::Fast });
+
+        let mut pool = PoolConfig::new(self.pool_size);
+        pool.timeouts = Timeouts {
+            wait: Some(timeout),
+            create: Some(timeout),
+            recycle: Some(timeout)
+        };
+        pg_cfg.pool = Some(pool);
+
+        let tls = TlsConnector::new().map_err(|er| ConfigError::Pool(er.to_string()))?;
+        pg_cfg.create_pool(Some(Runtime::Tokio1), MakeTlsConnector::new(tls))
+            .map_err(|er| ConfigError::Pool(er.to_string()))
+    }
+}
+
+impl PasswordConfig {
+    /// The hasher new passwords are hashed with.
+    pub fn hasher(&self) -> Result<Arc<dyn PasswordHasher>, ConfigError> {
+        match self.algorithm {
+            HashAlgorithm::Argon2id => match self.argon2_params() {
+                Ok(params) => Ok(Arc::new(Argon2idHasher::new(params))),
+                Err(er) => Err(ConfigError::Invalid { key: "password", message: er.to_string() }),
+            },
+            HashAlgorithm::Pbkdf2 => Ok(Arc::new(Pbkdf2Hasher::new(self.pbkdf2_rounds))),
+        }
+    }
+
+    fn argon2_params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+impl MailConfig {
+    pub fn mailer(&self) -> Box<dyn Mailer> {
+        match self.transport {
+            MailTransport::Log => Box::new(LogMailer::new(&self.from)),
+            MailTransport::File => Box::new(FileMailer::new(&self.from, &self.outbox)),
+        }
+    }
+}
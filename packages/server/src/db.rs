@@ -0,0 +1,72 @@
+use std::fmt;
+
+use deadpool_postgres::{Pool, PoolError};
+use postgres_types::ToSql;
+use tokio_postgres::{error::DbError, Row};
+
+/// Why [`query`] failed.
+#[derive(Debug)]
+pub enum QueryError {
+    /// No connection could be had in time, the pool is exhausted or
+    /// postgres is down. Routes answer these with a 503.
+    Unavailable(String),
+    /// Postgres answered with an error.
+    Postgres(tokio_postgres::Error)
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Unavailable(message) => write!(
+                f,
+                "The database is unavailable: {}",
+                message
+            ),
+            QueryError::Postgres(er) => write!(
+                f,
+                "{}",
+                er
+            ),
+        }
+    }
+}
+
+impl QueryError {
+    /// The error postgres raised, `None` if it never got that far.
+    pub fn as_db_error(&self) -> Option<&DbError> {
+        match self {
+            QueryError::Postgres(er) => er.as_db_error(),
+            QueryError::Unavailable(_) => None,
+        }
+    }
+}
+
+impl From<PoolError> for QueryError {
+    fn from(value: PoolError) -> Self {
+        QueryError::Unavailable(value.to_string())
+    }
+}
+
+impl From<tokio_postgres::Error> for QueryError {
+    fn from(value: tokio_postgres::Error) -> Self {
+        if value.is_closed() {
+            QueryError::Unavailable(value.to_string())
+        } else {
+            QueryError::Postgres(value)
+        }
+    }
+}
+
+/// A shorthand for running one query on a pooled connection, used by
+/// the postgres repositories.
+///
+/// # Example
+///
+/// ```rust
+/// let rows = db::query(&pool, "select * from find_by_id($1)", &[&id]).await?;
+/// ```
+pub async fn query(pool: &Pool, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, QueryError> {
+    let pg = pool.get().await?;
+    let stmt = pg.prepare(sql).await?;
+    Ok(pg.query(&stmt, params).await?)
+}
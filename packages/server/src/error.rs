@@ -0,0 +1,265 @@
+use std::fmt;
+
+use rocket::{
+    catch, http::Status, response::{self, Responder}, serde::{json::Json, Serialize}, Request
+};
+
+use crate::{
+    account::error::AccountError, session::error::SessionError, thread::error::ThreadError, token::error::TokenError,
+    validate::{FieldError, ValidationErrors}
+};
+
+/// The one error type every route answers with, so clients always get
+/// the same json body:
+///
+/// ```json
+/// { "code": "username_taken", "message": "The username zeljko is taken.", "field": "username" }
+/// ```
+///
+/// `field` is only there when the error is about a single input. Failed
+/// validation also lists every problem in `errors`:
+///
+/// ```json
+/// { "code": "validation_failed", "message": "2 fields are invalid.", "errors": [{ "field": "username", "message": ".." }, ..] }
+/// ```
+///
+/// Database errors are logged but never shown to the client.
+///
+/// # Example
+///
+/// ```rust
+/// use crate::error::ApiError;
+///
+/// #[get("/account/<username>")]
+/// pub async fn account_profile(username: &str, repos: &State<Repositories>) -> Result<Json<AccountProfile>, ApiError> {
+///     let acc = AccountConfig::new(repos).find("username", username).await?;
+///     Ok(Json(AccountProfile::from(acc)))
+/// }
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub enum ApiError {
+    Account(AccountError),
+    Session(SessionError),
+    Thread(ThreadError),
+    Token(TokenError),
+    Validation { field: &'static str, message: String },
+    /// The input failed [`crate::validate`], one entry per field.
+    Invalid(ValidationErrors),
+    Database(String),
+    /// The database could not be reached in time, answered with a 503.
+    Unavailable(String),
+    /// Anything rocket answered on its own (no route, bad json, ..).
+    Status(Status)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [FieldError]
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Account(er) => write!(f, "{}", er),
+            ApiError::Session(er) => write!(f, "{}", er),
+            ApiError::Thread(er) => write!(f, "{}", er),
+            ApiError::Token(er) => write!(f, "{}", er),
+            ApiError::Validation { message, .. } => write!(f, "{}", message),
+            ApiError::Invalid(errors) => write!(f, "{}", errors),
+            ApiError::Database(_) => write!(f, "Something went wrong on our side, please try again later."),
+            ApiError::Unavailable(_) => write!(f, "The service is unavailable right now, please try again later."),
+            ApiError::Status(status) => write!(f, "{}", status.reason_lossy()),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::Account(er) => er.status(),
+            ApiError::Session(er) => er.status(),
+            ApiError::Thread(er) => er.status(),
+            ApiError::Token(er) => er.status(),
+            ApiError::Validation { .. } | ApiError::Invalid(_) => Status::BadRequest,
+            ApiError::Database(_) => Status::InternalServerError,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+            ApiError::Status(status) => *status,
+        }
+    }
+
+    /// A stable, machine readable name for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Account(er) => match er {
+                AccountError::UsernameTaken(_) => "username_taken",
+                AccountError::EmailTaken(_) => "email_taken",
+                AccountError::InvalidFormat(_) => "invalid_format",
+                AccountError::AccountNotFound(_) => "account_not_found",
+                AccountError::WrongPassword => "wrong_password",
+                AccountError::Unauthenticated => "unauthenticated",
+                AccountError::SessionExpired => "session_expired",
+                AccountError::MissingPermission(_) => "missing_permission",
+                AccountError::MissingScope(_) => "missing_scope",
+                AccountError::SessionRequired => "session_required",
+                AccountError::NotSubordinate(_) => "not_subordinate",
+                AccountError::RegistrationClosed => "registration_closed",
+                AccountError::Banned => "banned",
+                AccountError::InvalidToken => "invalid_token",
+                AccountError::Unverified => "unverified",
+                AccountError::AlreadyVerified => "already_verified",
+                AccountError::TwoFactorEnabled => "two_factor_enabled",
+                AccountError::TwoFactorDisabled => "two_factor_disabled",
+                AccountError::WrongCode => "wrong_code",
+                AccountError::TooManyRequests(_) => "too_many_requests",
+                AccountError::InvalidCsrfToken => "invalid_csrf_token",
+                AccountError::OidcDisabled => "oidc_disabled",
+                AccountError::OidcInvalid(_) => "oidc_invalid",
+                AccountError::OidcUnavailable(_) => "oidc_unavailable",
+                AccountError::Database(_) => "internal_error",
+                AccountError::Unavailable(_) => "service_unavailable",
+            },
+            ApiError::Session(er) => match er {
+                SessionError::SessionNotFound(_) => "session_not_found",
+                SessionError::Database(_) => "internal_error",
+                SessionError::Unavailable(_) => "service_unavailable",
+            },
+            ApiError::Thread(er) => match er {
+                ThreadError::ThreadNotFound(_) => "thread_not_found",
+                ThreadError::InvalidFormat(_) => "invalid_format",
+                ThreadError::Database(_) => "internal_error",
+                ThreadError::Unavailable(_) => "service_unavailable",
+            },
+            ApiError::Token(er) => match er {
+                TokenError::TokenNotFound(_) => "token_not_found",
+                TokenError::InvalidScope(_) => "invalid_scope",
+                TokenError::Database(_) => "internal_error",
+                TokenError::Unavailable(_) => "service_unavailable",
+            },
+            ApiError::Validation { .. } | ApiError::Invalid(_) => "validation_failed",
+            ApiError::Database(_) => "internal_error",
+            ApiError::Unavailable(_) => "service_unavailable",
+            ApiError::Status(status) => match status.code {
+                400 => "bad_request",
+                401 => "unauthenticated",
+                403 => "forbidden",
+                404 => "not_found",
+                409 => "conflict",
+                422 => "unprocessable_entity",
+                429 => "too_many_requests",
+                503 => "service_unavailable",
+                _ if status.code >= 500 => "internal_error",
+                _ => "request_failed",
+            },
+        }
+    }
+
+    /// Seconds a client should wait before trying again, sent as the
+    /// `Retry-After` header.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            ApiError::Account(AccountError::TooManyRequests(retry_after)) => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// The input the error is about, if it's about a single one.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            ApiError::Account(AccountError::UsernameTaken(_)) => Some("username"),
+            ApiError::Account(AccountError::EmailTaken(_)) => Some("email"),
+            ApiError::Account(AccountError::WrongPassword) => Some("password"),
+            ApiError::Account(AccountError::WrongCode) => Some("code"),
+            ApiError::Token(TokenError::InvalidScope(_)) => Some("scopes"),
+            ApiError::Validation { field, .. } => Some(field),
+            ApiError::Invalid(errors) => match errors.errors() {
+                [er] => Some(er.field),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(value: ValidationErrors) -> Self {
+        ApiError::Invalid(value)
+    }
+}
+
+impl From<AccountError> for ApiError {
+    fn from(value: AccountError) -> Self {
+        match value {
+            AccountError::Database(message) => ApiError::Database(message),
+            AccountError::Unavailable(message) => ApiError::Unavailable(message),
+            er => ApiError::Account(er),
+        }
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(value: SessionError) -> Self {
+        match value {
+            SessionError::Database(message) => ApiError::Database(message),
+            SessionError::Unavailable(message) => ApiError::Unavailable(message),
+            er => ApiError::Session(er),
+        }
+    }
+}
+
+impl From<ThreadError> for ApiError {
+    fn from(value: ThreadError) -> Self {
+        match value {
+            ThreadError::Database(message) => ApiError::Database(message),
+            ThreadError::Unavailable(message) => ApiError::Unavailable(message),
+            er => ApiError::Thread(er),
+        }
+    }
+}
+
+impl From<TokenError> for ApiError {
+    fn from(value: TokenError) -> Self {
+        match value {
+            TokenError::Database(message) => ApiError::Database(message),
+            TokenError::Unavailable(message) => ApiError::Unavailable(message),
+            er => ApiError::Token(er),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        if let ApiError::Database(message) | ApiError::Unavailable(message) = &self {
+            eprintln!("[Api] {} {} failed: {}", req.method(), req.uri(), message);
+        }
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            field: self.field(),
+            errors: match &self {
+                ApiError::Invalid(errors) => errors.errors(),
+                _ => &[],
+            }
+        };
+        let mut res = (self.status(), Json(body)).respond_to(req)?;
+        if let Some(retry_after) = self.retry_after() {
+            res.set_raw_header("Retry-After", retry_after.to_string());
+        }
+        Ok(res)
+    }
+}
+
+/// Answers everything rocket itself errors on with the same json body,
+/// request guards leave their [`ApiError`] in the request's local cache.
+#[catch(default)]
+pub fn default_catcher(status: Status, req: &Request<'_>) -> ApiError {
+    match req.local_cache(|| None::<ApiError>) {
+        Some(er) => er.clone(),
+        None => ApiError::Status(status),
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use async_trait::async_trait;
+use rocket::tokio::fs;
+
+/// A plain text mail, e.g. a password reset link.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Could not send mail: {}",
+            self.0
+        )
+    }
+}
+
+/// Delivers mail to accounts, picked with `transport` in the
+/// `[app.mail]` table (see [`crate::config::MailConfig`]). Managed by
+/// rocket as `Box<dyn Mailer>`.
+///
+/// # Example
+///
+/// ```rust
+/// #[post("/ping")]
+/// pub async fn ping(mailer: &State<Box<dyn Mailer>>) -> Result<(), ApiError> {
+///     mailer.send(Mail { to: "zeljko@gmail.com".into(), subject: "Ping".into(), body: "Pong".into() }).await?;
+///     Ok(())
+/// }
+/// ```
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
+
+/// Prints every mail to stdout, for local development.
+pub struct LogMailer {
+    from: String
+}
+
+impl LogMailer {
+    pub fn new(from: &str) -> Self {
+        LogMailer { from: from.to_string() }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        println!("[Mail] From: {}\n[Mail] To: {}\n[Mail] Subject: {}\n{}", self.from, mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
+
+/// Writes every mail as an `.eml` file into a directory, for local
+/// development without a mail server.
+pub struct FileMailer {
+    from: String,
+    dir: PathBuf
+}
+
+impl FileMailer {
+    pub fn new(from: &str, dir: &str) -> Self {
+        FileMailer { from: from.to_string(), dir: PathBuf::from(dir) }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        fs::create_dir_all(&self.dir).await.map_err(|er| MailError(er.to_string()))?;
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let path = self.dir.join(format!("{}-{}.eml", sent_at, mail.to.replace(['/', '\\'], "_")));
+        let eml = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from, mail.to, mail.subject, mail.body
+        );
+        fs::write(&path, eml).await.map_err(|er| MailError(er.to_string()))?;
+        println!("[Mail] Wrote {}", path.display());
+        Ok(())
+    }
+}
@@ -1,55 +1,203 @@
-/// A simple blog system written in rust. I made the restapi with basic 
-/// authentication(cookie auth). 
-/// 
-/// Please make sure the secure, and httponly flags are enabled. 
-/// 
-/// My first actual project in Rust.
-/// 
-/// * what it does not support
-/// multiple devices...
+//! A simple blog system written in rust. I made the restapi with basic
+//! authentication(cookie auth, or bearer tokens for scripts and apps).
+//!
+//! The session cookie is private (encrypted with `secret_key`), its
+//! secure, httponly and samesite flags live in the `[session_cookie]`
+//! table of Rocket.toml. Generate your own `secret_key`, release builds
+//! refuse to start with the sample one.
+//!
+//! Requests other than GET made with the cookie (and html forms) need a
+//! csrf token tied to the session, logging out and the POST of
+//! `/api/thread/retrieve` included. It goes in the `X-CSRF-Token` header
+//! or the first form field `csrf_token`. The frontend gets it from
+//! `/api/session/csrf` (see session::csrf).
+//!
+//! My first actual project in Rust.
+//!
+//! Every login gets its own session, so an account can stay logged
+//! in on multiple devices at once.
+//!
+//! Errors always come back as json with a fitting status code, e.g.
+//! `409 {"code": "email_taken", "message": "..", "field": "email"}`
+//! (see error::ApiError). Input is checked before it reaches the
+//! database (see validate), failed checks list every invalid field.
+//!
+//! Settings live in the `[app]` table of Rocket.toml (see
+//! config::AppConfig), the database can also be set with the usual
+//! `PG_HOST`, `PG_PORT`, `PG_DBNAME`, `PG_USER` and `PG_PASS` env vars.
+//! When no connection can be had in time requests are answered with
+//! a 503.
+//!
+//! The schema lives in `migrations/` and is embedded into the binary.
+//! It's applied on startup (unless `PG_MIGRATE_ON_START=false`) or with
+//! `server migrate`, the `schema_version` table tracks what ran.
+//!
+//! Passwords are hashed with Argon2id by default (`[app.password]`),
+//! older PBKDF2 hashes still work and are upgraded on the next login.
+//!
+//! New accounts confirm their email before they can post (`verify_email`
+//! in `[app]`). Confirmation and password reset links are mailed through
+//! `[app.mail]` (see mail::Mailer), locally they're printed or written
+//! to `outbox/`.
+//!
+//! Failed logins slow down further guesses per account and per ip
+//! (`[app.login]`), locked accounts get an unlock link mailed. The ip
+//! is the one of the connection, behind a proxy set rocket's
+//! `ip_header` and turn on `trust_ip_header` in `[app]`.
+//!
+//! Logging in also works without a password, through a link mailed to
+//! the account.
+//!
+//! With `[app.oidc]` enabled accounts can also sign in with an OpenID
+//! Connect provider (authorization code + PKCE). The first login links
+//! the account with the same verified email, or creates one.
+//!
+//! Any account can turn on two-factor authentication with an
+//! authenticator app (TOTP), logins then take a code or one of the
+//! recovery codes as a second step.
+//!
+//! Storage sits behind the repository traits (see repository::Repositories).
+//! `APP_BACKEND=memory` runs the server without postgres, everything is
+//! kept in the process and forgotten on restart.
+//!
+//! REST API REQUESTS
+//! * ACCOUNTS *
+//!   /api/account/new POST
+//!   /api/account/me GET, PATCH (a new email takes current_password), DELETE
+//!   /api/account/verify POST (token from the confirmation mail)
+//!   /api/account/verify/resend POST (once a minute)
+//!   /api/account/unlock POST (token from the lockout mail)
+//!   /api/account/login/two-factor POST (second login step with 2FA)
+//!   /api/account/login/link POST (mails a passwordless login link)
+//!   /api/account/login/link/redeem POST (token from the link, sets the cookie)
+//!   /api/account/oidc/login GET (redirects to the OpenID Connect provider)
+//!   /api/account/oidc/callback GET (where the provider sends the browser back)
+//!   /api/account/two-factor POST (enroll), /confirm, /disable POST (current password required)
+//!   /api/account/password POST (current password required, ends other sessions)
+//!   /api/account/password/forgot POST (mails a reset link)
+//!   /api/account/password/reset POST (token from the link, ends every session)
+//!   /api/account/{username} GET
+//!   /api/account/{username}/ban, /unban POST (admin)
+//!   /api/account/{username}/promote, /demote POST (owner)
+//!
+//! * SESSIONS *
+//!   /api/session/token POST (json login, bearer + refresh token)
+//!   /api/session/two-factor POST (second step with 2FA)
+//!   /api/session/csrf GET (csrf token for cookie requests, changes with every login)
+//!   /api/session/refresh POST
+//!   /api/session GET
+//!   /api/session/{id} DELETE
+//!   /api/session/others DELETE
+//!
+//! * TOKENS * (personal access tokens, e.g. for CI)
+//!   /api/token GET, POST
+//!   /api/token/{id} DELETE
+//!
+//! * THREADS *
+//!   /api/thread/new POST
+//!   /api/thread/retrieve POST (json or msgpack)
+//!   /api/thread/{id} GET (json or msgpack)
+//!   /api/thread/{id} PATCH, DELETE (author or moderator, see account::enums::Permission)
+//!   /api/thread/{id}/restore POST (moderator)
 
-/// REST API REQUESTS
-/// * ACCOUNTS * 
-/// /api/account/new POST
+use std::process;
 
-/// * THREADS * 
-/// /api/thread/new POST 
-/// /api/thread/retrieve POST 
-/// /api/thread/{id}
-
-
-use account::{config::AccountConfig};
-use deadpool_postgres::{Config, ManagerConfig, RecyclingMethod, Runtime};
-
-use rocket::{figment::providers::{ Env, Toml}, serde::Deserialize, routes};
-use tokio_postgres::NoTls;
+use account::oidc::OidcClient;
+use config::{AppConfig, Backend};
+use repository::Repositories;
+use rocket::{catchers, figment::Figment, Build, Rocket};
+use session::{cookie::CookieConfig, csrf::CsrfFairing};
 
 mod account;
+mod config;
+mod db;
+mod error;
+mod mail;
+mod migrate;
+mod repository;
 mod session;
+#[cfg(test)]
+mod tests;
 mod thread;
+mod token;
+mod validate;
 
 #[rocket::main]
-async fn main() -> Result<(), rocket::Error> { 
+async fn main() {
+    let figment = AppConfig::figment();
+    let config = match AppConfig::from_figment(&figment) {
+        Ok(config) => config,
+        Err(errors) => {
+            for er in errors {
+                eprintln!("[Config] {}", er);
+            }
+            process::exit(1);
+        },
+    };
+    let hasher = match config.password.hasher() {
+        Ok(hasher) => hasher,
+        Err(er) => {
+            eprintln!("[Config] {}", er);
+            process::exit(1);
+        },
+    };
+
+    let is_migrate = std::env::args().nth(1).as_deref() == Some("migrate");
+    if config.backend == Backend::Memory {
+        if is_migrate {
+            eprintln!("[Migrate] The memory backend has nothing to migrate.");
+            process::exit(1);
+        }
+        launch(rocket(figment, config, Repositories::memory(hasher))).await;
+        return;
+    }
+
+    let pool = match config.database.pool() {
+        Ok(pool) => pool,
+        Err(er) => {
+            eprintln!("[Config] {}", er);
+            process::exit(1);
+        },
+    };
 
-    // Postgres Database
-    let pg_dbname = Some(dotenv::var("PG_DBNAME").expect("PG_DBNAME NOT SET"));
-    let pg_user = Some(dotenv::var("PG_USER").expect("PG_USER NOT SET"));
-    let pg_pass = Some(dotenv::var("PG_PASS").expect("PG_PASS NOT SET"));let pg_port: Option<u16> = Some(dotenv::var("PG_PORT").expect("PG_PORT NOT SET").parse().unwrap());
-    let mut pg_cfg = Config::new();
-    pg_cfg.dbname = pg_dbname;
-    pg_cfg.user = pg_user;
-    pg_cfg.password = pg_pass;
-    pg_cfg.port = pg_port;
-    pg_cfg.manager = Some(ManagerConfig { recycling_method: RecyclingMethod::Fast });
+    // `server migrate` only migrates the database and exits.
+    if is_migrate {
+        match migrate::run(&pool).await {
+            Ok(applied) if applied.is_empty() => println!("[Migrate] The database is up to date."),
+            Ok(applied) => println!("[Migrate] Applied {} migration(s).", applied.len()),
+            Err(er) => {
+                eprintln!("[Migrate] {}", er);
+                process::exit(1);
+            },
+        }
+        return;
+    }
 
-    let pool = pg_cfg.create_pool(Some(Runtime::Tokio1), NoTls).unwrap();
+    let mut server = rocket(figment, config.clone(), Repositories::postgres(pool.clone(), hasher));
+    if config.database.migrate_on_start {
+        server = server.attach(migrate::fairing(pool));
+    }
+    launch(server).await;
+}
 
-    let acc_cfg = AccountConfig::new(&pool);
+async fn launch(rocket: Rocket<Build>) {
+    if let Err(er) = rocket.launch().await {
+        eprintln!("{}", er);
+        process::exit(1);
+    }
+}
 
-    let _rocket = rocket::build()
+fn rocket(figment: Figment, config: AppConfig, repos: Repositories) -> Rocket<Build> {
+    rocket::custom(figment)
+    .register("/", catchers![error::default_catcher])
+    .attach(CookieConfig::fairing())
+    .attach(CsrfFairing)
     .mount("/api", account::routes::routes())
-    .mount("/api", thread::routes::routes()).manage(pool)
-        .ignite().await?
-        .launch().await?;
-    Ok(())
-}
\ No newline at end of file
+    .mount("/api", session::routes::routes())
+    .mount("/api", thread::routes::routes())
+    .mount("/api", token::routes::routes())
+    .manage(config.mail.mailer())
+    .manage(OidcClient::new(&config.oidc))
+    .manage(repos)
+    .manage(config)
+}
@@ -0,0 +1,96 @@
+use deadpool_postgres::{Client, Pool};
+use rocket::fairing::AdHoc;
+
+use crate::db::QueryError;
+
+/// A schema change, embedded into the binary so a deploy can never
+/// run with a schema it doesn't expect.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    sql: &'static str
+}
+
+/// Every migration, in order. Never change one that has shipped, add
+/// a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "schema", sql: include_str!("../migrations/0001_schema.sql") },
+    Migration { version: 2, name: "functions", sql: include_str!("../migrations/0002_functions.sql") },
+    Migration { version: 3, name: "password", sql: include_str!("../migrations/0003_password.sql") },
+    Migration { version: 4, name: "one_time_tokens", sql: include_str!("../migrations/0004_one_time_tokens.sql") },
+    Migration { version: 5, name: "email_verification", sql: include_str!("../migrations/0005_email_verification.sql") },
+    Migration { version: 6, name: "login_lookup", sql: include_str!("../migrations/0006_login_lookup.sql") },
+    Migration { version: 7, name: "login_attempts", sql: include_str!("../migrations/0007_login_attempts.sql") },
+    Migration { version: 8, name: "two_factor", sql: include_str!("../migrations/0008_two_factor.sql") },
+    Migration { version: 9, name: "external_identities", sql: include_str!("../migrations/0009_external_identities.sql") },
+    Migration { version: 10, name: "login_reservations", sql: include_str!("../migrations/0010_login_reservations.sql") },
+    Migration { version: 11, name: "revoke_access_tokens", sql: include_str!("../migrations/0011_revoke_access_tokens.sql") },
+    Migration { version: 12, name: "case_insensitive_accounts", sql: include_str!("../migrations/0012_case_insensitive_accounts.sql") },
+    Migration { version: 13, name: "session_hashes", sql: include_str!("../migrations/0013_session_hashes.sql") },
+    Migration { version: 14, name: "reclaim_accounts", sql: include_str!("../migrations/0014_reclaim_accounts.sql") },
+    Migration { version: 15, name: "email_changes", sql: include_str!("../migrations/0015_email_changes.sql") },
+    Migration { version: 16, name: "verified_emails", sql: include_str!("../migrations/0016_verified_emails.sql") },
+];
+
+/// Keeps two servers that start at the same time from migrating twice.
+const LOCK_ID: i64 = 0x626c6f67;
+
+/// Applies every migration newer than the `schema_version` table says
+/// the database is at, each in its own transaction. Returns what was
+/// applied.
+///
+/// # Example
+///
+/// ```rust
+/// let applied = migrate::run(&pool).await?;
+/// println!("applied {} migrations", applied.len());
+/// ```
+pub async fn run(pool: &Pool) -> Result<Vec<&'static Migration>, QueryError> {
+    let mut pg = pool.get().await?;
+    pg.query("SELECT pg_advisory_lock($1)", &[&LOCK_ID]).await?;
+    let applied = apply(&mut pg).await;
+    pg.query("SELECT pg_advisory_unlock($1)", &[&LOCK_ID]).await?;
+    applied
+}
+
+async fn apply(pg: &mut Client) -> Result<Vec<&'static Migration>, QueryError> {
+    pg.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            applied_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )"
+    ).await?;
+    let current: i32 = pg.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[]).await?.get(0);
+
+    let mut applied = vec![];
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        let tx = pg.transaction().await?;
+        if let Err(er) = tx.batch_execute(migration.sql).await {
+            eprintln!("[Migrate] {:04}_{} failed, nothing of it was applied.", migration.version, migration.name);
+            return Err(er.into());
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES ($1, $2)",
+            &[&migration.version, &migration.name]
+        ).await?;
+        tx.commit().await?;
+        println!("[Migrate] Applied {:04}_{}", migration.version, migration.name);
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+/// Migrates the database before the server starts and refuses to
+/// start if that fails.
+pub fn fairing(pool: Pool) -> AdHoc {
+    AdHoc::try_on_ignite("Migrations", |rocket| async move {
+        match run(&pool).await {
+            Ok(_) => Ok(rocket),
+            Err(er) => {
+                eprintln!("[Migrate] Refusing to start: {}", er);
+                Err(rocket)
+            },
+        }
+    })
+}
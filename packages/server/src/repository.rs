@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use deadpool_postgres::Pool;
+
+use crate::{
+    account::{
+        config::Account,
+        hasher::PasswordHasher,
+        lockout::LoginAttempts,
+        oidc::ExternalIdentity,
+        onetime::OneTimeToken,
+        repository::{
+            AccountRepository, ExternalIdentityRepository, LoginAttemptRepository, MemoryAccountRepository,
+            MemoryExternalIdentityRepository, MemoryLoginAttemptRepository, MemoryOneTimeTokenRepository,
+            MemoryTwoFactorRepository, OneTimeTokenRepository, PgAccountRepository, PgExternalIdentityRepository,
+            PgLoginAttemptRepository, PgOneTimeTokenRepository, PgTwoFactorRepository, TwoFactorRepository
+        },
+        twofactor::TwoFactor
+    },
+    session::{config::Session, repository::{MemorySessionRepository, PgSessionRepository, SessionRepository}},
+    thread::{config::Thread, repository::{MemoryThreadRepository, PgThreadRepository, ThreadRepository}},
+    token::{config::AccessToken, repository::{MemoryTokenRepository, PgTokenRepository, TokenRepository}}
+};
+
+/// Where accounts, sessions, threads and tokens are kept. Managed by
+/// rocket, [`AccountConfig`](crate::account::config::AccountConfig)
+/// borrows it so nothing else has to know which backend is in use.
+///
+/// Pick the backend with `backend` in the `[app]` table (see
+/// [`crate::config::Backend`]).
+pub struct Repositories {
+    pub accounts: Box<dyn AccountRepository>,
+    pub sessions: Box<dyn SessionRepository>,
+    pub threads: Box<dyn ThreadRepository>,
+    pub tokens: Box<dyn TokenRepository>,
+    pub one_time: Box<dyn OneTimeTokenRepository>,
+    pub attempts: Box<dyn LoginAttemptRepository>,
+    pub two_factor: Box<dyn TwoFactorRepository>,
+    pub identities: Box<dyn ExternalIdentityRepository>,
+    /// How passwords end up in [`Repositories::accounts`].
+    pub hasher: Arc<dyn PasswordHasher>
+}
+
+impl Repositories {
+    /// Repositories backed by the functions in migrations/.
+    pub fn postgres(pool: Pool, hasher: Arc<dyn PasswordHasher>) -> Self {
+        Repositories {
+            accounts: Box::new(PgAccountRepository::new(pool.clone())),
+            sessions: Box::new(PgSessionRepository::new(pool.clone())),
+            threads: Box::new(PgThreadRepository::new(pool.clone())),
+            tokens: Box::new(PgTokenRepository::new(pool.clone())),
+            one_time: Box::new(PgOneTimeTokenRepository::new(pool.clone())),
+            attempts: Box::new(PgLoginAttemptRepository::new(pool.clone())),
+            two_factor: Box::new(PgTwoFactorRepository::new(pool.clone())),
+            identities: Box::new(PgExternalIdentityRepository::new(pool)),
+            hasher
+        }
+    }
+
+    /// Repositories that keep everything in the process, for local
+    /// development and tests. Everything is gone once the server stops.
+    pub fn memory(hasher: Arc<dyn PasswordHasher>) -> Self {
+        let store = MemoryStore::shared();
+        Repositories {
+            accounts: Box::new(MemoryAccountRepository::new(store.clone())),
+            sessions: Box::new(MemorySessionRepository::new(store.clone())),
+            threads: Box::new(MemoryThreadRepository::new(store.clone())),
+            tokens: Box::new(MemoryTokenRepository::new(store.clone())),
+            one_time: Box::new(MemoryOneTimeTokenRepository::new(store.clone())),
+            attempts: Box::new(MemoryLoginAttemptRepository::new(store.clone())),
+            two_factor: Box::new(MemoryTwoFactorRepository::new(store.clone())),
+            identities: Box::new(MemoryExternalIdentityRepository::new(store)),
+            hasher
+        }
+    }
+}
+
+/// The tables of the in-memory backend. All repositories share one
+/// store so deleting or banning an account reaches its sessions, tokens
+/// and threads just like the postgres functions do.
+#[derive(Default)]
+pub struct MemoryStore {
+    pub accounts: Vec<Account>,
+    pub sessions: Vec<Session>,
+    pub threads: Vec<Thread>,
+    pub tokens: Vec<AccessToken>,
+    pub one_time: Vec<OneTimeToken>,
+    pub login_attempts: Vec<LoginAttempts>,
+    pub two_factor: Vec<TwoFactor>,
+    /// `(account_id, code_hash)`
+    pub recovery_codes: Vec<(String, String)>,
+    pub external_identities: Vec<ExternalIdentity>
+}
+
+impl MemoryStore {
+    pub fn shared() -> SharedStore {
+        SharedStore(Arc::new(Mutex::new(MemoryStore::default())))
+    }
+}
+
+/// A [`MemoryStore`] behind a mutex, cloning it is cheap.
+#[derive(Clone)]
+pub struct SharedStore(Arc<Mutex<MemoryStore>>);
+
+impl SharedStore {
+    /// Locks the store. Never hold it across an `.await`.
+    pub fn lock(&self) -> MutexGuard<'_, MemoryStore> {
+        // every write leaves the store consistent, so a panic elsewhere
+        // doesn't make it unusable.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -2,47 +2,305 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use nanoid::nanoid;
 use postgres_types::ToSql;
-use rocket::{serde::{Serialize, Deserialize}};
+use rocket::{serde::{json::Json, Serialize, Deserialize}, request::{FromRequest, Outcome}, Request, Responder};
+use sha2::{Digest, Sha256};
+use tokio_postgres::Row;
 
-use crate::account::config::AccountConfig;
+use crate::{account::{config::AccountConfig, twofactor::PendingLogin}, config::AppConfig};
 
-#[derive(Debug, Deserialize, Serialize, ToSql)]
+use super::error::SessionError;
+
+#[derive(Debug, Clone, Deserialize, Serialize, ToSql)]
 #[serde(crate = "rocket::serde")]
 pub struct Session {
+    /// Sha256 of [`Session::token`], what sessions are stored and looked
+    /// up by.
+    #[serde(skip_serializing)]
     pub session_id: String,
+    /// The cookie value (or access token), only known right after the
+    /// session was created or rotated. Never hand it out after login.
+    #[serde(skip)]
+    pub token: String,
     pub account_id: String,
-    pub expires_in: String
+    pub expires_in: String,
+    /// Public id used to list and revoke sessions.
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+    pub last_seen: String,
+    /// Sha256 of the refresh token, only set for bearer sessions.
+    #[serde(skip_serializing)]
+    pub refresh_hash: Option<String>,
+    pub refresh_expires_in: Option<String>
 }
 
 impl Session {
+    /// How long a session stays valid unless [`Session::set_lifetime`]
+    /// says otherwise, one week (in ms).
+    pub const LIFETIME: u128 = 604800000;
+    /// How long a bearer access token stays valid, one hour (in ms).
+    /// Refresh tokens live for the session lifetime.
+    pub const ACCESS_LIFETIME: u128 = 3600000;
+    /// How often `last_seen` is written back, one minute (in ms).
+    const TOUCH_INTERVAL: u128 = 60000;
+
     pub fn new(account_id: &str) -> Session {
-        let mut session = Session::default();
-        session.account_id = account_id.to_string();
-        session
+        Session {
+            account_id: account_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Constructs a session for API clients. The session token is used
+    /// as the (short lived) access token, the returned refresh token is
+    /// only stored as a hash as well.
+    pub fn new_bearer(account_id: &str, lifetime: u128) -> (Session, String) {
+        let mut session = Session::new(account_id);
+        let refresh_token = session.rotate(lifetime);
+        (session, refresh_token)
+    }
+
+    /// Lets the session (or the refresh token of a bearer session) run
+    /// out `lifetime` ms after it was created.
+    pub fn set_lifetime(&mut self, lifetime: u128) {
+        let created_at = self.created_at.parse::<u128>().unwrap_or_else(|_| Session::now());
+        let expires_in = (created_at + lifetime).to_string();
+        match self.refresh_hash {
+            Some(_) => self.refresh_expires_in = Some(expires_in),
+            None => self.expires_in = expires_in,
+        }
+    }
+
+    /// Milliseconds until the session expires, 0 once it has.
+    pub fn time_left(&self) -> u128 {
+        self.expires_in.parse::<u128>().unwrap_or(0).saturating_sub(Session::now())
+    }
+
+    /// Hands out a new access and refresh token for the same session.
+    fn rotate(&mut self, lifetime: u128) -> String {
+        let now = Session::now();
+        let refresh_token = nanoid!(48);
+        self.token = nanoid!();
+        self.session_id = Session::hash(&self.token);
+        self.expires_in = (now + Session::ACCESS_LIFETIME).to_string();
+        self.refresh_hash = Some(Session::hash(&refresh_token));
+        self.refresh_expires_in = Some((now + lifetime).to_string());
+        refresh_token
     }
 
-    pub async fn save(&self, cfg: AccountConfig<'_>) {
-        let sql = "SELECT create_session($1, $2, $3)";
-        // rushed...
-        let query = cfg.quik_query(sql, & [&self.session_id, &self.account_id, &self.expires_in]).await;
-        match query {
-            Ok(_) => {
-                println!("[Session] Created session with account id ({})", &self.account_id);
-            },
-            Err(er) => {
-                println!("{:#?}", er.as_db_error());
-            },
+    /// Trades a refresh token for a new access and refresh token, the old
+    /// pair stops working.
+    pub async fn refresh(cfg: &AccountConfig<'_>, refresh_token: &str, lifetime: u128) -> Result<(Session, String), SessionError> {
+        let old_hash = Session::hash(refresh_token);
+        let mut session = cfg.repos.sessions.find_by_refresh(&old_hash).await?;
+        if Session::is_past(session.refresh_expires_in.as_deref().unwrap_or_default()) {
+            let _ = session.revoke(cfg).await;
+            return Err(SessionError::SessionNotFound("refresh token".to_string()));
         }
+
+        let refresh_token = session.rotate(lifetime);
+        cfg.repos.sessions.rotate(&old_hash, &session).await?;
+        Ok((session, refresh_token))
+    }
+
+    /// Sha256 (hex) of a token, tokens are never stored in plain text.
+    pub fn hash(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+
+    /// Attaches the [`Device`] the session was created from.
+    pub fn set_device(&mut self, device: Device) {
+        self.user_agent = device.user_agent;
+        self.ip = device.ip;
+    }
+
+    pub async fn save(&self, cfg: AccountConfig<'_>) -> Result<(), SessionError> {
+        cfg.repos.sessions.create(self).await?;
+        println!("[Session] Created session with account id ({})", &self.account_id);
+        Ok(())
+    }
+
+    /// Finds a session by its plain token, expired sessions are returned
+    /// as well so check [`Session::is_expired`].
+    pub async fn find(cfg: &AccountConfig<'_>, token: &str) -> Result<Session, SessionError> {
+        cfg.repos.sessions.find(&Session::hash(token)).await
+    }
+
+    /// Lists every session of an account, most recently used first.
+    pub async fn list(cfg: &AccountConfig<'_>, account_id: &str) -> Result<Vec<Session>, SessionError> {
+        cfg.repos.sessions.list(account_id).await
+    }
+
+    /// Ends this session by deleting it, the
+    /// [`AuthedAccount`](crate::account::guard::AuthedAccount) guard looks
+    /// every session up so the cookie stops working right away.
+    pub async fn revoke(&self, cfg: &AccountConfig<'_>) -> Result<(), SessionError> {
+        cfg.repos.sessions.revoke(&self.session_id).await?;
+        println!("[Session] Revoked session {} of account ({})", &self.id, &self.account_id);
+        Ok(())
+    }
+
+    /// Ends the session with the public `id`, as long as it belongs to
+    /// the account.
+    pub async fn remove(cfg: &AccountConfig<'_>, account_id: &str, id: &str) -> Result<(), SessionError> {
+        cfg.repos.sessions.remove(account_id, id).await?;
+        println!("[Session] Revoked session {} of account ({})", id, account_id);
+        Ok(())
+    }
+
+    /// Ends every session of the account except this one.
+    pub async fn remove_others(&self, cfg: &AccountConfig<'_>) -> Result<(), SessionError> {
+        cfg.repos.sessions.remove_others(&self.account_id, &self.session_id).await?;
+        println!("[Session] Revoked other sessions of account ({})", &self.account_id);
+        Ok(())
+    }
+
+    /// Ends every session of the account, e.g. after its password was
+    /// reset.
+    pub async fn remove_all(cfg: &AccountConfig<'_>, account_id: &str) -> Result<(), SessionError> {
+        cfg.repos.sessions.remove_all(account_id).await?;
+        println!("[Session] Revoked all sessions of account ({})", account_id);
+        Ok(())
+    }
+
+    /// Updates `last_seen`, at most once per [`Session::TOUCH_INTERVAL`].
+    pub async fn touch(&mut self, cfg: &AccountConfig<'_>) {
+        let now = Session::now();
+        let last_seen = self.last_seen.parse::<u128>().unwrap_or(0);
+        if now < last_seen + Session::TOUCH_INTERVAL {
+            return;
+        }
+
+        self.last_seen = now.to_string();
+        if let Err(er) = cfg.repos.sessions.touch(&self.session_id, &self.last_seen).await {
+            println!("[Session] Could not update last seen: {}", er);
+        }
+    }
+
+    /// `expires_in` holds the unix time (in ms) the session stops working.
+    pub fn is_expired(&self) -> bool {
+        Session::is_past(&self.expires_in)
+    }
+
+    fn is_past(timestamp: &str) -> bool {
+        match timestamp.parse::<u128>() {
+            Ok(timestamp) => timestamp <= Session::now(),
+            Err(_) => true,
+        }
+    }
+
+    fn now() -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
     }
 }
 
 impl Default for Session {
     fn default() -> Self {
-        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
-        Self { 
-            session_id: nanoid!(), 
+        let created_at = Session::now();
+        let token = nanoid!();
+        Self {
+            session_id: Session::hash(&token),
+            token,
             account_id: Default::default(),
-            expires_in: ((created_at + 604800000)).to_string() // one week from now
+            expires_in: (created_at + Session::LIFETIME).to_string(), // one week from now
+            id: nanoid!(10),
+            user_agent: None,
+            ip: None,
+            created_at: created_at.to_string(),
+            last_seen: created_at.to_string(),
+            refresh_hash: None,
+            refresh_expires_in: None
+        }
+    }
+}
+
+impl From<&Row> for Session {
+    fn from(value: &Row) -> Self {
+        Session {
+            session_id: value.get(0),
+            token: String::new(),
+            account_id: value.get(1),
+            expires_in: value.get(2),
+            id: value.get(3),
+            user_agent: value.get(4),
+            ip: value.get(5),
+            created_at: value.get(6),
+            last_seen: value.get(7),
+            refresh_hash: value.get(8),
+            refresh_expires_in: value.get(9)
+        }
+    }
+}
+
+/// What `POST /api/session/token` and `/api/session/refresh` answer with.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    /// Seconds until the access token expires.
+    pub expires_in: u128
+}
+
+impl TokenResponse {
+    pub fn new(session: &Session, refresh_token: String) -> Self {
+        TokenResponse {
+            access_token: session.token.clone(),
+            refresh_token,
+            token_type: "Bearer",
+            expires_in: Session::ACCESS_LIFETIME / 1000
         }
     }
-}
\ No newline at end of file
+}
+
+/// What `POST /api/session/token` answers with, accounts with 2FA get
+/// a 202 and finish the login at `POST /api/session/two-factor`.
+#[derive(Responder)]
+pub enum LoginResponse {
+    Token(Json<TokenResponse>),
+    #[response(status = 202)]
+    TwoFactor(Json<PendingLogin>)
+}
+
+/// The body of `POST /api/session/refresh`.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshRequest {
+    pub refresh_token: String
+}
+
+/// A session as listed by `GET /api/session`.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request.
+    pub current: bool
+}
+
+/// The device a request comes from, stored with new sessions.
+///
+/// Never fails, missing headers are simply `None`.
+pub struct Device {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Device {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ip = match req.rocket().state::<AppConfig>() {
+            Some(app) => app.client_ip(req),
+            None => req.remote().map(|remote| remote.ip()),
+        };
+        Outcome::Success(Device {
+            user_agent: req.headers().get_one("User-Agent").map(|agent| agent.to_string()),
+            ip: ip.map(|ip| ip.to_string())
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use rocket::{
+    config::Config, fairing::AdHoc, http::{Cookie, CookieJar, SameSite},
+    serde::Deserialize, time::Duration
+};
+
+use super::config::Session;
+
+/// The key shipped in Rocket.toml, everyone has a copy of it.
+const SAMPLE_SECRET_KEY: &str = "dflY9FR2vYArOmFhupMLn/hyB6lYDCTXz4yaQX89XVg=";
+
+/// Name of the (private) cookie holding the session id.
+pub const SESSION_COOKIE: &str = "sid";
+
+/// Name of the readable cookie that only tells the frontend someone
+/// is logged in, it holds nothing secret.
+pub const HINT_COOKIE: &str = "logged_in";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+    None
+}
+
+impl From<CookieSameSite> for SameSite {
+    fn from(value: CookieSameSite) -> Self {
+        match value {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+            CookieSameSite::None => SameSite::None,
+        }
+    }
+}
+
+/// The `[session_cookie]` table of Rocket.toml.
+///
+/// ```toml
+/// [default.session_cookie]
+/// secure = true
+/// http_only = true
+/// same_site = "lax"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CookieConfig {
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: CookieSameSite,
+    pub domain: Option<String>
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            http_only: true,
+            same_site: CookieSameSite::Lax,
+            domain: None
+        }
+    }
+}
+
+impl CookieConfig {
+    /// Hands out the session as an encrypted (private) cookie that lives
+    /// exactly as long as the session.
+    pub fn add(&self, jar: &CookieJar<'_>, session: &Session) {
+        let max_age = Duration::milliseconds(session.time_left() as i64);
+        let mut sid = Cookie::build((SESSION_COOKIE, session.token.clone()))
+            .path("/")
+            .secure(self.secure)
+            .http_only(self.http_only)
+            .same_site(self.same_site.into())
+            .max_age(max_age);
+        let mut hint = Cookie::build((HINT_COOKIE, "true"))
+            .path("/")
+            .secure(self.secure)
+            .same_site(self.same_site.into())
+            .max_age(max_age);
+        if let Some(domain) = &self.domain {
+            sid = sid.domain(domain.clone());
+            hint = hint.domain(domain.clone());
+        }
+        jar.add_private(sid);
+        jar.add(hint);
+    }
+
+    pub fn remove(&self, jar: &CookieJar<'_>) {
+        jar.remove_private(Cookie::build(SESSION_COOKIE).path("/"));
+        jar.remove(Cookie::build(HINT_COOKIE).path("/"));
+    }
+
+    /// Reads `[session_cookie]` into managed state and refuses to start a
+    /// release build with the sample `secret_key`.
+    pub fn fairing() -> AdHoc {
+        AdHoc::try_on_ignite("Session Cookies", |rocket| async {
+            let figment = rocket.figment();
+            if figment.profile() == Config::RELEASE_PROFILE {
+                let secret_key = figment.extract_inner::<String>("secret_key").unwrap_or_default();
+                if secret_key == SAMPLE_SECRET_KEY {
+                    eprintln!("[Session] Refusing to start: the sample secret_key from Rocket.toml is used in release.");
+                    eprintln!("[Session] Generate your own with `openssl rand -base64 32`.");
+                    return Err(rocket);
+                }
+            }
+
+            let config = match figment.extract_inner::<CookieConfig>("session_cookie") {
+                Ok(config) => config,
+                Err(er) if er.missing() => CookieConfig::default(),
+                Err(er) => {
+                    eprintln!("[Session] Invalid [session_cookie] config: {}", er);
+                    return Err(rocket);
+                }
+            };
+            Ok(rocket.manage(config))
+        })
+    }
+}
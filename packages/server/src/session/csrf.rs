@@ -0,0 +1,196 @@
+use data_encoding::BASE64URL_NOPAD;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{uri::Origin, Cookie, CookieJar, Header, Method, SameSite},
+    serde::{json::Json, Serialize},
+    Build, Data, Request, Responder, Rocket
+};
+use sha2::{Digest, Sha256};
+
+use crate::{account::error::AccountError, error::ApiError};
+
+use super::cookie::{CookieConfig, SESSION_COOKIE};
+
+/// The header scripts on the frontend's origin send the token in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The form field html forms send the token in, it has to be the first
+/// field (see [`CsrfFairing`]).
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// Name of the (private) cookie the token of a logged out browser is
+/// tied to.
+pub const ANONYMOUS_COOKIE: &str = "csrf";
+
+/// Where rejected requests are sent, no route lives there so the
+/// default catcher answers with the error the fairing left behind.
+const REJECTED_URI: &str = "/api/session/csrf/rejected";
+
+/// How much of a form body is searched for [`CSRF_FIELD`], as much as
+/// rocket lets a fairing peek at.
+const PEEK_LIMIT: usize = 512;
+
+/// Hands out and checks csrf tokens. A token is an HMAC of the session
+/// cookie, or of an anonymous cookie before logging in, so it changes
+/// with every login and nothing has to be stored. Managed by rocket.
+///
+/// # Example
+///
+/// ```rust
+/// let token = csrf.token(jar, cookies); // GET /api/session/csrf
+/// assert!(csrf.verify(jar, &token));
+/// ```
+pub struct Csrf {
+    key: [u8; 32]
+}
+
+impl Csrf {
+    /// The token of the browser behind `jar`, a browser without any
+    /// cookie gets the anonymous one first.
+    pub fn token(&self, jar: &CookieJar<'_>, cookies: &CookieConfig) -> String {
+        let binding = match Csrf::binding(jar) {
+            Some(binding) => binding,
+            None => {
+                let mut bytes = [0u8; 32];
+                OsRng.fill_bytes(&mut bytes);
+                let value = BASE64URL_NOPAD.encode(&bytes);
+                let mut cookie = Cookie::build((ANONYMOUS_COOKIE, value.clone()))
+                    .path("/")
+                    .secure(cookies.secure)
+                    .http_only(true)
+                    .same_site(SameSite::Lax);
+                if let Some(domain) = &cookies.domain {
+                    cookie = cookie.domain(domain.clone());
+                }
+                jar.add_private(cookie);
+                format!("anonymous:{}", value)
+            },
+        };
+        BASE64URL_NOPAD.encode(&self.mac(&binding).finalize().into_bytes())
+    }
+
+    /// Whether `token` belongs to the browser behind `jar`, compared in
+    /// constant time.
+    pub fn verify(&self, jar: &CookieJar<'_>, token: &str) -> bool {
+        let (Some(binding), Ok(tag)) = (Csrf::binding(jar), BASE64URL_NOPAD.decode(token.trim().as_bytes())) else {
+            return false;
+        };
+        self.mac(&binding).verify_slice(&tag).is_ok()
+    }
+
+    /// What the token is tied to, the session wins over the anonymous
+    /// cookie.
+    fn binding(jar: &CookieJar<'_>) -> Option<String> {
+        if let Some(sid) = jar.get_private(SESSION_COOKIE) {
+            return Some(format!("session:{}", sid.value()));
+        }
+        jar.get_private(ANONYMOUS_COOKIE).map(|anonymous| format!("anonymous:{}", anonymous.value()))
+    }
+
+    fn mac(&self, binding: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("hmac takes keys of any length");
+        mac.update(binding.as_bytes());
+        mac
+    }
+}
+
+/// Enforces csrf tokens on every state changing request (anything but
+/// GET, HEAD and OPTIONS) a browser could be tricked into sending:
+/// requests carrying the session cookie, and html forms. Requests with
+/// a bearer token and json or msgpack posts without the cookie can't be
+/// forged cross site and are let through.
+///
+/// The token comes in the [`CSRF_HEADER`] header or, for html forms, in
+/// the [`CSRF_FIELD`] field, which has to be the first one. Rejected
+/// requests get a 403 `invalid_csrf_token`.
+///
+/// Also manages [`Csrf`], keyed from `secret_key`.
+pub struct CsrfFairing;
+
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF Tokens",
+            kind: Kind::Ignite | Kind::Request
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let key = match rocket.figment().extract_inner::<String>("secret_key") {
+            Ok(secret_key) => Sha256::new().chain_update("csrf:").chain_update(secret_key).finalize().into(),
+            // rocket generates a key of its own then, the tokens only
+            // last until the next restart like the cookies do.
+            Err(_) => {
+                let mut key = [0u8; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            },
+        };
+        Ok(rocket.manage(Csrf { key }))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
+        let bearer = req.headers().get_one("Authorization").is_some_and(|header| header.starts_with("Bearer "));
+        if matches!(req.method(), Method::Get | Method::Head | Method::Options) || bearer {
+            return;
+        }
+        let cookie_authed = req.cookies().get_private(SESSION_COOKIE).is_some();
+        // what a cross site <form> can send without asking first.
+        let form = req.content_type().is_none_or(|content_type| {
+            content_type.is_form() || content_type.is_form_data() || content_type.is_plain()
+        });
+        if !cookie_authed && !form {
+            return;
+        }
+
+        let mut token = req.headers().get_one(CSRF_HEADER).map(|token| token.to_string());
+        if token.is_none() && req.content_type().is_some_and(|content_type| content_type.is_form()) {
+            let peeked = String::from_utf8_lossy(data.peek(PEEK_LIMIT).await);
+            token = peeked.split('&')
+                .filter_map(|field| field.split_once('='))
+                .find(|(name, _)| *name == CSRF_FIELD)
+                .map(|(_, value)| value.to_string());
+        }
+        let valid = match (req.rocket().state::<Csrf>(), token) {
+            (Some(csrf), Some(token)) => csrf.verify(req.cookies(), &token),
+            _ => false,
+        };
+        if !valid {
+            println!("[Csrf] Rejected {} {}", req.method(), req.uri());
+            req.local_cache(|| Some(ApiError::from(AccountError::InvalidCsrfToken)));
+            req.set_uri(Origin::parse(REJECTED_URI).expect("a valid origin"));
+        }
+    }
+}
+
+/// What `GET /api/session/csrf` answers with.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CsrfToken {
+    pub csrf_token: String
+}
+
+/// Lets the frontend read the token cross origin, with its cookies.
+#[derive(Responder)]
+pub struct CsrfResponse {
+    inner: Json<CsrfToken>,
+    allow_origin: Header<'static>,
+    allow_credentials: Header<'static>,
+    vary: Header<'static>,
+    cache_control: Header<'static>
+}
+
+impl CsrfResponse {
+    pub fn new(csrf_token: String, frontend_origin: &str) -> Self {
+        CsrfResponse {
+            inner: Json(CsrfToken { csrf_token }),
+            allow_origin: Header::new("Access-Control-Allow-Origin", frontend_origin.trim_end_matches('/').to_string()),
+            allow_credentials: Header::new("Access-Control-Allow-Credentials", "true"),
+            vary: Header::new("Vary", "Origin, Cookie"),
+            cache_control: Header::new("Cache-Control", "no-store")
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+use rocket::http::Status;
+
+use crate::db::QueryError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SessionError {
+    SessionNotFound(String),
+    Database(String),
+    Unavailable(String)
+}
+
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::SessionNotFound(id) => write!(
+                f,
+                "Could not find session with the id '{}'",
+                id
+            ),
+            SessionError::Database(message) => write!(
+                f,
+                "Database error: {}",
+                message
+            ),
+            SessionError::Unavailable(message) => write!(
+                f,
+                "{}",
+                message
+            ),
+        }
+    }
+}
+
+impl SessionError {
+    pub fn parse_db_error(er: &QueryError, id: &str) -> SessionError {
+        if let QueryError::Unavailable(message) = er {
+            return SessionError::Unavailable(message.clone());
+        }
+        match er.as_db_error() {
+            Some(error) if error.code().code() == "42P15" => {
+                SessionError::SessionNotFound(id.to_string())
+            },
+            Some(error) => SessionError::Database(error.message().to_string()),
+            None => SessionError::Database(er.to_string())
+        }
+    }
+
+    /// The http status a route should answer with for this error.
+    pub fn status(&self) -> Status {
+        match self {
+            SessionError::SessionNotFound(_) => Status::NotFound,
+            SessionError::Database(_) => Status::InternalServerError,
+            SessionError::Unavailable(_) => Status::ServiceUnavailable,
+        }
+    }
+}
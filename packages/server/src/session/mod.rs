@@ -1 +1,6 @@
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod cookie;
+pub mod csrf;
+pub mod error;
+pub mod repository;
+pub mod routes;
\ No newline at end of file
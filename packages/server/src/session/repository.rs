@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+
+use crate::{db, repository::SharedStore};
+
+use super::{config::Session, error::SessionError};
+
+/// Stores and looks up sessions, see [`crate::repository::Repositories`].
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create(&self, session: &Session) -> Result<(), SessionError>;
+
+    /// Finds a session by its `session_id` (the token's hash), expired
+    /// ones included.
+    async fn find(&self, session_id: &str) -> Result<Session, SessionError>;
+
+    /// Finds a bearer session by the hash of its refresh token.
+    async fn find_by_refresh(&self, refresh_hash: &str) -> Result<Session, SessionError>;
+
+    /// Stores the new tokens of `session`, the one holding `old_hash`.
+    async fn rotate(&self, old_hash: &str, session: &Session) -> Result<(), SessionError>;
+
+    /// Every session of an account, most recently used first.
+    async fn list(&self, account_id: &str) -> Result<Vec<Session>, SessionError>;
+
+    /// Deletes a session, does nothing if it's already gone.
+    async fn revoke(&self, session_id: &str) -> Result<(), SessionError>;
+
+    /// Deletes the session with the public `id`, as long as it belongs
+    /// to the account.
+    async fn remove(&self, account_id: &str, id: &str) -> Result<(), SessionError>;
+
+    async fn remove_others(&self, account_id: &str, keep_session_id: &str) -> Result<(), SessionError>;
+
+    /// Deletes every session of the account, e.g. after a password reset.
+    async fn remove_all(&self, account_id: &str) -> Result<(), SessionError>;
+
+    async fn touch(&self, session_id: &str, last_seen: &str) -> Result<(), SessionError>;
+}
+
+pub struct PgSessionRepository {
+    pool: Pool
+}
+
+impl PgSessionRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgSessionRepository { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PgSessionRepository {
+    async fn create(&self, session: &Session) -> Result<(), SessionError> {
+        let sql = "SELECT create_session($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+        let response = db::query(&self.pool, sql, &[
+            &session.session_id, &session.account_id, &session.expires_in,
+            &session.id, &session.user_agent, &session.ip, &session.created_at,
+            &session.refresh_hash, &session.refresh_expires_in
+        ]).await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, &session.id)),
+        }
+    }
+
+    async fn find(&self, session_id: &str) -> Result<Session, SessionError> {
+        match db::query(&self.pool, "select * from find_session($1)", &[&session_id]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Session::from(row)),
+                None => Err(SessionError::SessionNotFound(session_id.to_string()))
+            },
+            Err(er) => Err(SessionError::parse_db_error(&er, session_id)),
+        }
+    }
+
+    async fn find_by_refresh(&self, refresh_hash: &str) -> Result<Session, SessionError> {
+        match db::query(&self.pool, "select * from find_session_by_refresh($1)", &[&refresh_hash]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Session::from(row)),
+                None => Err(SessionError::SessionNotFound("refresh token".to_string()))
+            },
+            Err(er) => Err(SessionError::parse_db_error(&er, "refresh token")),
+        }
+    }
+
+    async fn rotate(&self, old_hash: &str, session: &Session) -> Result<(), SessionError> {
+        let sql = "select rotate_session($1, $2, $3, $4, $5)";
+        let response = db::query(&self.pool, sql, &[
+            &old_hash, &session.session_id, &session.expires_in,
+            &session.refresh_hash, &session.refresh_expires_in
+        ]).await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, "refresh token")),
+        }
+    }
+
+    async fn list(&self, account_id: &str) -> Result<Vec<Session>, SessionError> {
+        match db::query(&self.pool, "select * from find_sessions($1)", &[&account_id]).await {
+            Ok(res) => Ok(res.iter().map(Session::from).collect()),
+            Err(er) => Err(SessionError::parse_db_error(&er, account_id)),
+        }
+    }
+
+    async fn revoke(&self, session_id: &str) -> Result<(), SessionError> {
+        match db::query(&self.pool, "select revoke_session($1)", &[&session_id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, session_id)),
+        }
+    }
+
+    async fn remove(&self, account_id: &str, id: &str) -> Result<(), SessionError> {
+        match db::query(&self.pool, "select delete_session($1, $2)", &[&account_id, &id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn remove_others(&self, account_id: &str, keep_session_id: &str) -> Result<(), SessionError> {
+        match db::query(&self.pool, "select delete_other_sessions($1, $2)", &[&account_id, &keep_session_id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, keep_session_id)),
+        }
+    }
+
+    async fn remove_all(&self, account_id: &str) -> Result<(), SessionError> {
+        match db::query(&self.pool, "select delete_sessions($1)", &[&account_id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, account_id)),
+        }
+    }
+
+    async fn touch(&self, session_id: &str, last_seen: &str) -> Result<(), SessionError> {
+        match db::query(&self.pool, "select touch_session($1, $2)", &[&session_id, &last_seen]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(SessionError::parse_db_error(&er, session_id)),
+        }
+    }
+}
+
+pub struct MemorySessionRepository {
+    store: SharedStore
+}
+
+impl MemorySessionRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemorySessionRepository { store }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for MemorySessionRepository {
+    async fn create(&self, session: &Session) -> Result<(), SessionError> {
+        // like a database, only the hash is kept.
+        let stored = Session { token: String::new(), ..session.clone() };
+        self.store.lock().sessions.push(stored);
+        Ok(())
+    }
+
+    async fn find(&self, session_id: &str) -> Result<Session, SessionError> {
+        let store = self.store.lock();
+        match store.sessions.iter().find(|session| session.session_id == session_id) {
+            Some(session) => Ok(session.clone()),
+            None => Err(SessionError::SessionNotFound(session_id.to_string())),
+        }
+    }
+
+    async fn find_by_refresh(&self, refresh_hash: &str) -> Result<Session, SessionError> {
+        let store = self.store.lock();
+        match store.sessions.iter().find(|session| session.refresh_hash.as_deref() == Some(refresh_hash)) {
+            Some(session) => Ok(session.clone()),
+            None => Err(SessionError::SessionNotFound("refresh token".to_string())),
+        }
+    }
+
+    async fn rotate(&self, old_hash: &str, session: &Session) -> Result<(), SessionError> {
+        let mut store = self.store.lock();
+        match store.sessions.iter_mut().find(|stored| stored.refresh_hash.as_deref() == Some(old_hash)) {
+            Some(stored) => {
+                stored.session_id = session.session_id.clone();
+                stored.expires_in = session.expires_in.clone();
+                stored.refresh_hash = session.refresh_hash.clone();
+                stored.refresh_expires_in = session.refresh_expires_in.clone();
+                Ok(())
+            },
+            None => Err(SessionError::SessionNotFound("refresh token".to_string())),
+        }
+    }
+
+    async fn list(&self, account_id: &str) -> Result<Vec<Session>, SessionError> {
+        let store = self.store.lock();
+        let mut sessions: Vec<Session> = store.sessions.iter()
+            .filter(|session| session.account_id == account_id)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        Ok(sessions)
+    }
+
+    async fn revoke(&self, session_id: &str) -> Result<(), SessionError> {
+        self.store.lock().sessions.retain(|session| session.session_id != session_id);
+        Ok(())
+    }
+
+    async fn remove(&self, account_id: &str, id: &str) -> Result<(), SessionError> {
+        let mut store = self.store.lock();
+        let before = store.sessions.len();
+        store.sessions.retain(|session| !(session.account_id == account_id && session.id == id));
+        if store.sessions.len() == before {
+            return Err(SessionError::SessionNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn remove_others(&self, account_id: &str, keep_session_id: &str) -> Result<(), SessionError> {
+        self.store.lock().sessions.retain(|session| session.account_id != account_id || session.session_id == keep_session_id);
+        Ok(())
+    }
+
+    async fn remove_all(&self, account_id: &str) -> Result<(), SessionError> {
+        self.store.lock().sessions.retain(|session| session.account_id != account_id);
+        Ok(())
+    }
+
+    async fn touch(&self, session_id: &str, last_seen: &str) -> Result<(), SessionError> {
+        let mut store = self.store.lock();
+        if let Some(session) = store.sessions.iter_mut().find(|session| session.session_id == session_id) {
+            session.last_seen = last_seen.to_string();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use rocket::{http::{CookieJar, Status}, serde::json::Json, State, Route, routes, get, post, delete};
+
+use crate::{
+    account::{config::{AccountConfig, AccountLogin, TwoFactorLogin}, error::AccountError, guard::AuthedAccount, lockout::LoginLimiter, twofactor::LoginOutcome},
+    config::AppConfig,
+    error::ApiError,
+    repository::Repositories
+};
+
+use super::{
+    config::{Device, LoginResponse, RefreshRequest, Session, SessionInfo, TokenResponse},
+    cookie::CookieConfig,
+    csrf::{Csrf, CsrfResponse},
+    error::SessionError
+};
+
+/// The json login for scripts and apps, answers with a bearer
+/// access token and a refresh token instead of a cookie.
+#[post("/session/token", format = "json", data = "<login>")]
+pub async fn session_token(login: Json<AccountLogin>, device: Device, limiter: LoginLimiter<'_>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<LoginResponse, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    match cfg.auth(login.method(), &login.identifier, &login.password, &limiter).await? {
+        LoginOutcome::Session(authed) => Ok(LoginResponse::Token(bearer(cfg, &authed, device, app).await?)),
+        LoginOutcome::TwoFactor(pending) => Ok(LoginResponse::TwoFactor(Json(pending))),
+    }
+}
+
+/// The second step of the json login for accounts with 2FA.
+#[post("/session/two-factor", format = "json", data = "<login>")]
+pub async fn session_two_factor(login: Json<TwoFactorLogin>, device: Device, limiter: LoginLimiter<'_>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Json<TokenResponse>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    let authed = cfg.auth_two_factor(&login.pending_token, &login.code, &limiter).await?;
+    bearer(cfg, &authed, device, app).await
+}
+
+/// Swaps a successful login for a bearer session.
+async fn bearer(cfg: AccountConfig<'_>, authed: &Session, device: Device, app: &AppConfig) -> Result<Json<TokenResponse>, ApiError> {
+    let (mut session, refresh_token) = Session::new_bearer(&authed.account_id, app.session_lifetime_ms());
+    session.set_device(device);
+    session.save(cfg).await?;
+    Ok(Json(TokenResponse::new(&session, refresh_token)))
+}
+
+/// The csrf token the frontend sends with forms and other state
+/// changing requests, it changes with every login so fetch it again
+/// afterwards. Readable from `frontend_origin`.
+#[get("/session/csrf")]
+pub async fn session_csrf(jar: &CookieJar<'_>, csrf: &State<Csrf>, cookies: &State<CookieConfig>, app: &State<AppConfig>) -> CsrfResponse {
+    CsrfResponse::new(csrf.token(jar, cookies), &app.frontend_origin)
+}
+
+#[post("/session/refresh", format = "json", data = "<refresh>")]
+pub async fn session_refresh(refresh: Json<RefreshRequest>, app: &State<AppConfig>, repos: &State<Repositories>) -> Result<Json<TokenResponse>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    match Session::refresh(&cfg, &refresh.refresh_token, app.session_lifetime_ms()).await {
+        Ok((session, refresh_token)) => Ok(Json(TokenResponse::new(&session, refresh_token))),
+        Err(SessionError::SessionNotFound(_)) => Err(AccountError::SessionExpired.into()),
+        Err(er) => Err(er.into()),
+    }
+}
+
+#[get("/session")]
+pub async fn session_list(authed: AuthedAccount, repos: &State<Repositories>) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let current = authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    let sessions = Session::list(&cfg, authed.account.id()).await?;
+    Ok(Json(sessions.into_iter().map(|session| SessionInfo {
+        current: session.session_id == current.session_id,
+        session
+    }).collect()))
+}
+
+/// Logs out every device but the one making the request.
+#[delete("/session/others")]
+pub async fn session_revoke_others(authed: AuthedAccount, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    let current = authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    current.remove_others(&cfg).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/session/<id>")]
+pub async fn session_revoke(id: &str, authed: AuthedAccount, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    Session::remove(&cfg, authed.account.id(), id).await?;
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![session_token, session_two_factor, session_csrf, session_refresh, session_list, session_revoke_others, session_revoke]
+}
@@ -1,7 +0,0 @@
-CREATE TYPE "Rank" AS ENUM (
-    'None',
-    'Member',
-    'Moderator',
-    'Admin',
-    'Owner'
-);
\ No newline at end of file
@@ -0,0 +1,437 @@
+use rocket::{http::{ContentType, Header, Status}, serde::json::{serde_json::json, Value}};
+
+use crate::account::twofactor::TwoFactor;
+
+use super::{error_code, TestServer, PASSWORD, REMOTE};
+
+#[rocket::async_test]
+async fn register_and_login_with_the_cookie() {
+    let server = TestServer::new().await;
+    let profile = server.register("zeljko").await;
+    assert_eq!(profile["username"], "zeljko");
+    assert_eq!(profile["rank"], "Member");
+
+    let res = server.login_form("zeljko", PASSWORD).await;
+    assert_eq!(res.status(), Status::SeeOther);
+    let res = server.client.get("/api/account/me").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let me: Value = res.into_json().await.unwrap();
+    assert_eq!(me["email"], "zeljko@example.com");
+    assert!(me.get("password").is_none());
+}
+
+#[rocket::async_test]
+async fn register_checks_every_field() {
+    let server = TestServer::new().await;
+    let res = server.client.post("/api/account/new")
+        .json(&json!({ "username": "z!", "password": "short", "email": "nope" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::BadRequest);
+    let body: Value = res.into_json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["errors"].as_array().unwrap().len(), 3);
+}
+
+#[rocket::async_test]
+async fn register_refuses_taken_usernames() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.client.post("/api/account/new")
+        .json(&json!({ "username": "zeljko", "password": PASSWORD, "email": "other@example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Conflict);
+    assert_eq!(error_code(res).await, "username_taken");
+}
+
+#[rocket::async_test]
+async fn usernames_and_emails_are_taken_in_any_case() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.client.post("/api/account/new")
+        .json(&json!({ "username": "Zeljko", "password": PASSWORD, "email": "other@example.com" }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "username_taken");
+    let res = server.client.post("/api/account/new")
+        .json(&json!({ "username": "other", "password": PASSWORD, "email": "ZELJKO@example.com" }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "email_taken");
+
+    server.register("other").await;
+    let bearer = server.bearer("other").await;
+    let res = server.client.patch("/api/account/me")
+        .header(bearer.clone())
+        .json(&json!({ "username": "ZELJKO" }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "username_taken");
+    // the own name only changes its case.
+    let res = server.client.patch("/api/account/me")
+        .header(bearer)
+        .json(&json!({ "username": "Other", "email": "Other@example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn a_new_email_takes_the_password_and_a_confirmation() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    server.register("zeljko").await;
+    let token = server.mailed_token("zeljko@example.com");
+    server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+    let bearer = server.bearer("zeljko").await;
+
+    let change = |body: Value| server.client.patch("/api/account/me").header(bearer.clone()).json(&body);
+    let res = change(json!({ "email": "victim@example.com" })).dispatch().await;
+    assert_eq!(res.status(), Status::BadRequest);
+    assert_eq!(error_code(res).await, "validation_failed");
+    let res = change(json!({ "email": "victim@example.com", "current_password": "idontloveyou1" })).dispatch().await;
+    assert_eq!(error_code(res).await, "wrong_password");
+
+    let res = change(json!({ "email": "victim@example.com", "current_password": PASSWORD })).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let me: Value = res.into_json().await.unwrap();
+    assert_eq!(me["rank"], "None");
+    let token = server.mailed_token("victim@example.com");
+    let res = server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+    let profile: Value = res.into_json().await.unwrap();
+    assert_eq!(profile["rank"], "Member");
+
+    // a different case is still the same address.
+    let res = change(json!({ "email": "Victim@example.com" })).dispatch().await;
+    let me: Value = res.into_json().await.unwrap();
+    assert_eq!(me["rank"], "Member");
+}
+
+#[rocket::async_test]
+async fn wrong_password_is_refused() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.login_token("zeljko", "idontloveyou1").await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(error_code(res).await, "wrong_password");
+}
+
+#[rocket::async_test]
+async fn unknown_accounts_look_like_a_wrong_password() {
+    let server = TestServer::new().await;
+    for identifier in ["nobody", "nobody@example.com"] {
+        let res = server.login_token(identifier, PASSWORD).await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        assert_eq!(error_code(res).await, "wrong_password");
+    }
+}
+
+#[rocket::async_test]
+async fn logout_ends_the_session() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    server.login_form("zeljko", PASSWORD).await;
+    // a link can't log anyone out.
+    assert_eq!(server.client.get("/api/account/logout").dispatch().await.status(), Status::NotFound);
+    let res = server.client.post("/api/account/logout").dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(server.client.get("/api/account/me").dispatch().await.status(), Status::Ok);
+
+    let res = server.client.post("/api/account/logout")
+        .header(ContentType::Form)
+        .body(format!("csrf_token={}", server.csrf().await))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::SeeOther);
+    let res = server.client.get("/api/account/me").dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn new_accounts_confirm_their_email() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    assert_eq!(server.register("zeljko").await["rank"], "None");
+
+    let token = server.mailed_token("zeljko@example.com");
+    let res = server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let profile: Value = res.into_json().await.unwrap();
+    assert_eq!(profile["rank"], "Member");
+}
+
+#[rocket::async_test]
+async fn password_reset_ends_every_session() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+
+    // any case of the email works, like logging in.
+    let res = server.client.post("/api/account/password/forgot")
+        .json(&json!({ "email": "Zeljko@Example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Accepted);
+    let token = server.mailed_token("zeljko@example.com");
+    let res = server.client.post("/api/account/password/reset")
+        .json(&json!({ "token": token, "password": "iloveyou3" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+
+    let res = server.client.get("/api/account/me").header(bearer).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Unauthorized);
+    assert_eq!(server.login_token("zeljko", "iloveyou3").await.status(), Status::Ok);
+
+    // the link only works once.
+    let res = server.client.post("/api/account/password/reset")
+        .json(&json!({ "token": token, "password": "iloveyou4" }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "invalid_token");
+}
+
+#[rocket::async_test]
+async fn password_reset_revokes_access_tokens() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let pat = create_token(&server, &server.bearer("zeljko").await).await;
+
+    server.client.post("/api/account/password/forgot")
+        .json(&json!({ "email": "zeljko@example.com" }))
+        .dispatch().await;
+    let res = server.client.post("/api/account/password/reset")
+        .json(&json!({ "token": server.mailed_token("zeljko@example.com"), "password": "iloveyou3" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.client.get("/api/account/me").header(pat).dispatch().await.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn password_change_revokes_access_tokens() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let pat = create_token(&server, &bearer).await;
+
+    let res = server.client.post("/api/account/password")
+        .header(bearer.clone())
+        .json(&json!({ "current_password": PASSWORD, "new_password": "iloveyou3" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.client.get("/api/account/me").header(pat).dispatch().await.status(), Status::Unauthorized);
+    // the session that changed it stays.
+    assert_eq!(server.client.get("/api/account/me").header(bearer).dispatch().await.status(), Status::Ok);
+}
+
+/// A personal access token of `bearer`'s account, as its header.
+async fn create_token(server: &TestServer, bearer: &Header<'static>) -> Header<'static> {
+    let res = server.client.post("/api/token")
+        .header(bearer.clone())
+        .json(&json!({ "name": "ci", "scopes": ["account:read"] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Created);
+    let created: Value = res.into_json().await.unwrap();
+    let pat = Header::new("Authorization", format!("Bearer {}", created["token"].as_str().unwrap()));
+    assert_eq!(server.client.get("/api/account/me").header(pat.clone()).dispatch().await.status(), Status::Ok);
+    pat
+}
+
+#[rocket::async_test]
+async fn password_forgot_mails_once_in_a_while() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    for _ in 0..2 {
+        let res = server.client.post("/api/account/password/forgot")
+            .json(&json!({ "email": "zeljko@example.com" }))
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Accepted);
+    }
+    assert_eq!(server.mails("zeljko@example.com").len(), 1);
+}
+
+#[rocket::async_test]
+async fn mails_are_limited_per_ip() {
+    let server = TestServer::with(|config| {
+        config.login.ip_free_mails = 2;
+        config.login.max_delay = 60;
+    }).await;
+    let ask = |path: &'static str, n: u32| server.client.post(path)
+        .remote(REMOTE.parse().unwrap())
+        .json(&json!({ "email": format!("nobody{}@example.com", n) }));
+    assert_eq!(ask("/api/account/password/forgot", 0).dispatch().await.status(), Status::Accepted);
+    assert_eq!(ask("/api/account/login/link", 1).dispatch().await.status(), Status::Accepted);
+    for path in ["/api/account/password/forgot", "/api/account/login/link"] {
+        let res = ask(path, 2).dispatch().await;
+        assert_eq!(res.status(), Status::TooManyRequests);
+        assert_eq!(error_code(res).await, "too_many_requests");
+    }
+}
+
+#[rocket::async_test]
+async fn password_forgot_keeps_emails_secret() {
+    let server = TestServer::new().await;
+    let res = server.client.post("/api/account/password/forgot")
+        .json(&json!({ "email": "nobody@example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Accepted);
+    assert!(server.mails("nobody@example.com").is_empty());
+}
+
+#[rocket::async_test]
+async fn magic_link_logs_in_once() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    // any case of the email works, like logging in.
+    let res = server.client.post("/api/account/login/link")
+        .json(&json!({ "email": "Zeljko@Example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Accepted);
+    let token = server.mailed_token("zeljko@example.com");
+
+    let redeem = |csrf: String| server.client.post("/api/account/login/link/redeem")
+        .header(ContentType::Form)
+        .body(format!("csrf_token={}&token={}", csrf, token));
+    let res = redeem(server.csrf().await).dispatch().await;
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(server.client.get("/api/account/me").dispatch().await.status(), Status::Ok);
+
+    let res = redeem(server.csrf().await).dispatch().await;
+    assert_eq!(error_code(res).await, "invalid_token");
+}
+
+#[rocket::async_test]
+async fn magic_link_keeps_emails_secret() {
+    let server = TestServer::new().await;
+    let res = server.client.post("/api/account/login/link")
+        .json(&json!({ "email": "nobody@example.com" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Accepted);
+    assert!(server.mails("nobody@example.com").is_empty());
+}
+
+/// Enrolls `bearer`'s account in 2FA, returns the secret and the
+/// recovery codes.
+async fn enable_two_factor(server: &TestServer, bearer: &Header<'static>) -> (String, Vec<String>) {
+    let res = server.client.post("/api/account/two-factor")
+        .header(bearer.clone())
+        .json(&json!({ "password": PASSWORD }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let enrollment: Value = res.into_json().await.unwrap();
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+
+    let res = server.client.post("/api/account/two-factor/confirm")
+        .header(bearer.clone())
+        .json(&json!({ "code": TwoFactor::current_code(&secret), "password": PASSWORD }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let codes: Value = res.into_json().await.unwrap();
+    let codes = codes["recovery_codes"].as_array().unwrap().iter().map(|code| code.as_str().unwrap().to_string()).collect();
+    (secret, codes)
+}
+
+#[rocket::async_test]
+async fn two_factor_login_takes_a_code() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let (_, recovery_codes) = enable_two_factor(&server, &bearer).await;
+
+    let res = server.login_token("zeljko", PASSWORD).await;
+    assert_eq!(res.status(), Status::Accepted);
+    let pending: Value = res.into_json().await.unwrap();
+    let pending_token = pending["pending_token"].as_str().unwrap();
+
+    let res = server.client.post("/api/session/two-factor")
+        .json(&json!({ "pending_token": pending_token, "code": "000000" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(error_code(res).await, "wrong_code");
+
+    let res = server.client.post("/api/session/two-factor")
+        .json(&json!({ "pending_token": pending_token, "code": recovery_codes[0] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let tokens: Value = res.into_json().await.unwrap();
+    assert!(tokens["access_token"].is_string());
+
+    // a pending login can't be finished twice.
+    let res = server.client.post("/api/session/two-factor")
+        .json(&json!({ "pending_token": pending_token, "code": recovery_codes[1] }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "invalid_token");
+}
+
+#[rocket::async_test]
+async fn two_factor_login_refuses_accounts_banned_in_between() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let (secret, _) = enable_two_factor(&server, &bearer).await;
+    let res = server.login_token("zeljko", PASSWORD).await;
+    let pending: Value = res.into_json().await.unwrap();
+
+    let acc = server.repos().accounts.find("username", "zeljko").await.unwrap();
+    server.repos().accounts.set_banned(&acc, true).await.unwrap();
+    let res = server.client.post("/api/session/two-factor")
+        .json(&json!({ "pending_token": pending["pending_token"], "code": TwoFactor::current_code(&secret) }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "banned");
+
+    // 2FA turned off in the meantime skips the code, never the ban.
+    server.repos().two_factor.delete(acc.id()).await.unwrap();
+    let res = server.client.post("/api/session/two-factor")
+        .json(&json!({ "pending_token": pending["pending_token"], "code": "000000" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "banned");
+}
+
+#[rocket::async_test]
+async fn two_factor_enrollment_takes_the_password() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+
+    let enroll = |body: Value| server.client.post("/api/account/two-factor").header(bearer.clone()).json(&body);
+    for body in [json!({}), json!({ "password": "idontloveyou1" })] {
+        let res = enroll(body).dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        assert_eq!(error_code(res).await, "wrong_password");
+    }
+    let res = enroll(json!({ "password": PASSWORD })).dispatch().await;
+    let enrollment: Value = res.into_json().await.unwrap();
+    let code = TwoFactor::current_code(enrollment["secret"].as_str().unwrap());
+
+    let res = server.client.post("/api/account/two-factor/confirm")
+        .header(bearer.clone())
+        .json(&json!({ "code": code }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(error_code(res).await, "wrong_password");
+    let acc = server.repos().accounts.find("username", "zeljko").await.unwrap();
+    assert!(server.repos().two_factor.find(acc.id()).await.unwrap().is_some_and(|two_factor| !two_factor.enabled));
+}
+
+#[rocket::async_test]
+async fn two_factor_disable_takes_the_password() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    enable_two_factor(&server, &bearer).await;
+
+    let res = server.client.post("/api/account/two-factor/disable")
+        .header(bearer.clone())
+        .json(&json!({ "password": "idontloveyou1" }))
+        .dispatch().await;
+    assert_eq!(error_code(res).await, "wrong_password");
+    let res = server.client.post("/api/account/two-factor/disable")
+        .header(bearer)
+        .json(&json!({ "password": PASSWORD }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn banned_accounts_can_not_log_in() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let acc = server.repos().accounts.find("username", "zeljko").await.unwrap();
+    server.repos().accounts.set_banned(&acc, true).await.unwrap();
+
+    let res = server.login_token("zeljko", PASSWORD).await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "banned");
+}
@@ -0,0 +1,163 @@
+//! A stand-in OpenID Connect provider for the oidc tests. It serves the
+//! discovery document, its keys and a token endpoint over real http, the
+//! server's [`OidcClient`](crate::account::oidc::OidcClient) can't tell it
+//! apart from a real provider. Authorizing is skipped, a test hands the
+//! redirect to the provider to [`MockIssuer::authorize`] and gets the
+//! callback back.
+//!
+//! The keys in `keys/` were made for these tests only.
+
+use std::{collections::HashMap, net::TcpListener, sync::{Arc, Mutex}, time::Duration};
+
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use rocket::{
+    form::Form,
+    get, post, routes,
+    serde::json::{serde_json::json, Json, Value},
+    tokio::{net::TcpStream, time::sleep},
+    FromForm, Shutdown, State
+};
+use sha2::{Digest, Sha256};
+
+/// Signs the provider's id tokens, its public half is in the jwks.
+pub const ISSUER_KEY: &str = include_str!("keys/issuer.pem");
+/// A key the provider never published.
+pub const ATTACKER_KEY: &str = include_str!("keys/attacker.pem");
+/// The modulus of [`ISSUER_KEY`], base64url.
+const ISSUER_N: &str = "tDZcWj_5TemZheFY9k3KJ8upK9esHc6BJtkT8_CudyoTAfrWveLj-OKLcnD-gwxMq956zTFvNH4QC2Kq2kLaencbqhl_HamGsXq4W0B0-vjfjJ0NBV3xGEJhKwc8uu9i9ltpRUGpmB3bc1jVs1IZp3UK-Z9qgSbwElbYGT4MMW0J9VAOr8Ivfz5HZGsRh_Ke_L-P_slLB0rcbb2tuyoaekYuMXXp0ENKZqaptwc0ZtJ15MRsCgOaFHJpL4ftYwKHhLREMNmv02QFI9-JNSzXvj3XfWRyCBWMer9HEKdFT2JTfKxURZCg_F9H0dWiRfmcPzb9e-5icx8f8p7I3vzszQ";
+const KID: &str = "test";
+
+pub const CLIENT_ID: &str = "blog";
+
+/// A code handed out by [`MockIssuer::authorize`], waiting to be traded
+/// for an id token.
+struct Grant {
+    challenge: String,
+    redirect_uri: String,
+    id_token: String
+}
+
+struct Issuer {
+    url: String,
+    grants: Mutex<HashMap<String, Grant>>
+}
+
+/// Runs until dropped, see the module docs.
+pub struct MockIssuer {
+    pub url: String,
+    issuer: Arc<Issuer>,
+    shutdown: Shutdown
+}
+
+impl MockIssuer {
+    pub async fn start() -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}", port);
+        let issuer = Arc::new(Issuer { url: url.clone(), grants: Mutex::new(HashMap::new()) });
+        let figment = rocket::Config::figment()
+            .merge(("address", "127.0.0.1"))
+            .merge(("port", port))
+            .merge(("log_level", "off"));
+        let rocket = rocket::custom(figment)
+            .mount("/", routes![discovery, jwks, token])
+            .manage(issuer.clone())
+            .ignite().await
+            .expect("a valid mock issuer");
+        let shutdown = rocket.shutdown();
+        rocket::tokio::spawn(rocket.launch());
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        MockIssuer { url, issuer, shutdown }
+    }
+
+    /// What a provider does once the user logged in: takes the redirect
+    /// to it and answers with the callback path, its code trades for an
+    /// id token signed with `key`. `claims` are merged over the ones of
+    /// a valid token, which carries the nonce of the redirect.
+    pub fn authorize(&self, location: &str, claims: Value, key: &str) -> String {
+        let url = Url::parse(location).expect("an absolute redirect");
+        assert!(url.as_str().starts_with(&format!("{}/authorize?", self.url)), "{}", location);
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert!(params["scope"].split(' ').any(|scope| scope == "openid"));
+
+        let now = jsonwebtoken::get_current_timestamp();
+        let mut token = json!({
+            "iss": self.url,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": params["nonce"]
+        });
+        for (claim, value) in claims.as_object().expect("claims are an object") {
+            token[claim] = value.clone();
+        }
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(KID.to_string());
+        let key = EncodingKey::from_rsa_pem(key.as_bytes()).expect("a valid test key");
+        let id_token = encode(&header, &token, &key).unwrap();
+
+        let code = nanoid::nanoid!();
+        self.issuer.grants.lock().unwrap().insert(code.clone(), Grant {
+            challenge: params["code_challenge"].clone(),
+            redirect_uri: params["redirect_uri"].clone(),
+            id_token
+        });
+        let callback = Url::parse(&params["redirect_uri"]).unwrap();
+        format!("{}?code={}&state={}", callback.path(), code, params["state"])
+    }
+}
+
+impl Drop for MockIssuer {
+    fn drop(&mut self) {
+        self.shutdown.clone().notify();
+    }
+}
+
+#[get("/.well-known/openid-configuration")]
+fn discovery(issuer: &State<Arc<Issuer>>) -> Json<Value> {
+    Json(json!({
+        "issuer": issuer.url,
+        "authorization_endpoint": format!("{}/authorize", issuer.url),
+        "token_endpoint": format!("{}/token", issuer.url),
+        "jwks_uri": format!("{}/jwks", issuer.url),
+        "token_endpoint_auth_methods_supported": ["none"]
+    }))
+}
+
+#[get("/jwks")]
+fn jwks() -> Json<Value> {
+    Json(json!({
+        "keys": [{ "kty": "RSA", "kid": KID, "use": "sig", "alg": "RS256", "n": ISSUER_N, "e": "AQAB" }]
+    }))
+}
+
+#[derive(FromForm)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    code_verifier: String,
+    client_id: String
+}
+
+/// Every code works once and only with the verifier of its challenge.
+#[post("/token", data = "<request>")]
+fn token(request: Form<TokenRequest>, issuer: &State<Arc<Issuer>>) -> Result<Json<Value>, (rocket::http::Status, Json<Value>)> {
+    let invalid = || (rocket::http::Status::BadRequest, Json(json!({ "error": "invalid_grant" })));
+    let grant = issuer.grants.lock().unwrap().remove(&request.code).ok_or_else(invalid)?;
+    let challenge = BASE64URL_NOPAD.encode(&Sha256::digest(request.code_verifier.as_bytes()));
+    if request.grant_type != "authorization_code" || request.client_id != CLIENT_ID
+        || request.redirect_uri != grant.redirect_uri || challenge != grant.challenge {
+        return Err(invalid());
+    }
+    Ok(Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": grant.id_token })))
+}
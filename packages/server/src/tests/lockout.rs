@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use rocket::{futures::future::join_all, http::{Header, Status}, serde::json::serde_json::json, tokio::time::sleep};
+
+use super::{error_code, TestServer, PASSWORD, REMOTE};
+
+#[rocket::async_test]
+async fn failed_logins_slow_down_the_account() {
+    let server = TestServer::with(|config| {
+        config.login.free_attempts = 2;
+        config.login.max_delay = 60;
+    }).await;
+    server.register("zeljko").await;
+
+    for _ in 0..2 {
+        assert_eq!(server.login_token("zeljko", "idontloveyou1").await.status(), Status::Unauthorized);
+    }
+    // even the right password has to wait now.
+    let res = server.login_token("zeljko", PASSWORD).await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+    assert_eq!(res.headers().get_one("Retry-After"), Some("1"));
+    assert_eq!(error_code(res).await, "too_many_requests");
+}
+
+#[rocket::async_test]
+async fn the_wait_doubles_with_every_failure() {
+    let server = TestServer::with(|config| {
+        config.login.free_attempts = 2;
+        config.login.max_delay = 60;
+    }).await;
+    server.register("zeljko").await;
+
+    for _ in 0..2 {
+        server.login_token("zeljko", "idontloveyou1").await;
+    }
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.headers().get_one("Retry-After"), Some("1"));
+    sleep(Duration::from_millis(1100)).await;
+    assert_eq!(server.login_token("zeljko", "idontloveyou1").await.status(), Status::Unauthorized);
+    let res = server.login_token("zeljko", PASSWORD).await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+    assert_eq!(res.headers().get_one("Retry-After"), Some("2"));
+}
+
+#[rocket::async_test]
+async fn a_successful_login_resets_the_account() {
+    let server = TestServer::with(|config| config.login.free_attempts = 2).await;
+    server.register("zeljko").await;
+    for _ in 0..3 {
+        assert_eq!(server.login_token("zeljko", "idontloveyou1").await.status(), Status::Unauthorized);
+        assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Ok);
+    }
+}
+
+#[rocket::async_test]
+async fn successful_logins_do_not_count_against_the_ip() {
+    let server = TestServer::with(|config| config.login.ip_free_attempts = 2).await;
+    server.register("zeljko").await;
+    for _ in 0..4 {
+        assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Ok);
+    }
+}
+
+#[rocket::async_test]
+async fn parallel_guesses_get_no_extra_tries() {
+    let server = TestServer::with(|config| {
+        config.login.free_attempts = 3;
+        config.login.max_delay = 60;
+    }).await;
+    server.register("zeljko").await;
+
+    let guesses = (0..10).map(|n| server.login_token("zeljko", if n == 9 { PASSWORD } else { "idontloveyou1" }));
+    let statuses: Vec<Status> = join_all(guesses).await.iter().map(|res| res.status()).collect();
+    let checked = statuses.iter().filter(|status| **status != Status::TooManyRequests).count();
+    assert_eq!(checked, 3, "{:?}", statuses);
+}
+
+#[rocket::async_test]
+async fn parallel_guesses_get_no_extra_tries_per_ip() {
+    let server = TestServer::with(|config| config.login.ip_free_attempts = 3).await;
+    let guesses = (0..10).map(|n| server.client.post("/api/session/token")
+        .remote(REMOTE.parse().unwrap())
+        .json(&json!({ "identifier": format!("nobody{}", n), "password": PASSWORD }))
+        .dispatch());
+    let statuses: Vec<Status> = join_all(guesses).await.iter().map(|res| res.status()).collect();
+    assert_eq!(statuses.iter().filter(|status| **status == Status::Unauthorized).count(), 3, "{:?}", statuses);
+}
+
+#[rocket::async_test]
+async fn locked_accounts_get_an_unlock_link() {
+    let server = TestServer::with(|config| {
+        config.login.free_attempts = 2;
+        config.login.max_delay = 1;
+    }).await;
+    server.register("zeljko").await;
+
+    for _ in 0..2 {
+        server.login_token("zeljko", "idontloveyou1").await;
+    }
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::TooManyRequests);
+    let token = server.mailed_token("zeljko@example.com");
+    let res = server.client.post("/api/account/unlock").json(&json!({ "token": token })).dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn the_ip_header_can_not_dodge_the_ip_limit() {
+    let server = TestServer::with(|config| config.login.ip_free_attempts = 2).await;
+    let remote = REMOTE.parse().unwrap();
+    for n in 0..2 {
+        let res = server.client.post("/api/session/token")
+            .remote(remote)
+            .header(Header::new("X-Real-IP", format!("10.0.1.{}", n)))
+            .json(&json!({ "identifier": format!("nobody{}", n), "password": PASSWORD }))
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+    }
+    let res = server.client.post("/api/session/token")
+        .remote(remote)
+        .header(Header::new("X-Real-IP", "10.0.1.9"))
+        .json(&json!({ "identifier": "nobody", "password": PASSWORD }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+}
+
+#[rocket::async_test]
+async fn a_trusted_ip_header_tells_clients_apart() {
+    let server = TestServer::with(|config| {
+        config.login.ip_free_attempts = 2;
+        config.trust_ip_header = true;
+    }).await;
+    for n in 0..3 {
+        let res = server.client.post("/api/session/token")
+            .header(Header::new("X-Real-IP", format!("10.0.1.{}", n)))
+            .json(&json!({ "identifier": "nobody", "password": PASSWORD }))
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+    }
+}
+
+#[rocket::async_test]
+async fn password_checks_of_a_session_are_limited_too() {
+    let server = TestServer::with(|config| {
+        config.login.free_attempts = 2;
+        config.login.max_delay = 60;
+    }).await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+
+    let change = |current: &str| server.client.post("/api/account/password")
+        .header(bearer.clone())
+        .json(&json!({ "current_password": current, "new_password": "iloveyou3" }));
+    for _ in 0..2 {
+        assert_eq!(error_code(change("idontloveyou1").dispatch().await).await, "wrong_password");
+    }
+    assert_eq!(change(PASSWORD).dispatch().await.status(), Status::TooManyRequests);
+    let res = server.client.post("/api/account/two-factor/disable")
+        .header(bearer.clone())
+        .json(&json!({ "password": PASSWORD }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+    // a login shares the count.
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::TooManyRequests);
+}
@@ -0,0 +1,164 @@
+//! Route tests, run through rocket's local client against the memory
+//! backend. Every test gets its own server, store and outbox, mails are
+//! written there by the file mailer and read back for their links.
+//! OpenID Connect logins go to a [`issuer::MockIssuer`] of their own.
+
+use std::{fs, path::PathBuf, sync::Arc};
+
+use argon2::Params;
+use nanoid::nanoid;
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+    serde::json::{serde_json, Value}
+};
+
+use crate::{
+    account::hasher::Argon2idHasher,
+    config::{AppConfig, Backend, MailTransport},
+    repository::Repositories,
+    session::csrf::CSRF_HEADER
+};
+
+mod account;
+mod issuer;
+mod lockout;
+mod oidc;
+mod session;
+mod thread;
+mod token;
+
+pub const PASSWORD: &str = "iloveyou2";
+
+/// Where the logins of [`TestServer`] come from.
+pub const REMOTE: &str = "10.0.0.1:4000";
+
+/// A server of its own, see [`TestServer::new`].
+pub struct TestServer {
+    pub client: Client,
+    outbox: PathBuf
+}
+
+impl TestServer {
+    /// The default `[app]` with cheap password hashing, mails written to
+    /// a fresh outbox and email confirmation off.
+    pub async fn new() -> Self {
+        TestServer::with(|_| ()).await
+    }
+
+    /// Like [`TestServer::new`], `change` tweaks the config first.
+    pub async fn with(change: impl FnOnce(&mut AppConfig)) -> Self {
+        let outbox = std::env::temp_dir().join(format!("blog-test-{}", nanoid!(10)));
+        let mut config = AppConfig {
+            backend: Backend::Memory,
+            verify_email: false,
+            ..Default::default()
+        };
+        config.mail.transport = MailTransport::File;
+        config.mail.outbox = outbox.to_string_lossy().to_string();
+        change(&mut config);
+
+        let figment = rocket::Config::figment()
+            .merge(("secret_key", "hPRYyVRiMyxpw5sBB1XeCMN1kFsDCqKvBi2QJxBVHQk="))
+            .merge(("log_level", "off"))
+            // rocket's default, `trust_ip_header` alone decides.
+            .merge(("ip_header", "X-Real-IP"));
+        let hasher = Arc::new(Argon2idHasher::new(Params::new(64, 1, 1, None).unwrap()));
+        let client = Client::tracked(crate::rocket(figment, config, Repositories::memory(hasher))).await
+            .expect("a valid rocket instance");
+        TestServer { client, outbox }
+    }
+
+    pub fn repos(&self) -> &Repositories {
+        self.client.rocket().state::<Repositories>().expect("managed repositories")
+    }
+
+    pub fn app(&self) -> &AppConfig {
+        self.client.rocket().state::<AppConfig>().expect("a managed config")
+    }
+
+    /// Registers an account, `email` is `{username}@example.com`.
+    pub async fn register(&self, username: &str) -> Value {
+        let res = self.client.post("/api/account/new")
+            .json(&serde_json::json!({
+                "username": username,
+                "password": PASSWORD,
+                "email": format!("{}@example.com", username)
+            }))
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Created);
+        res.into_json().await.unwrap()
+    }
+
+    /// The csrf token of the client's cookies.
+    pub async fn csrf(&self) -> String {
+        let res = self.client.get("/api/session/csrf").dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        let body: Value = res.into_json().await.unwrap();
+        body["csrf_token"].as_str().unwrap().to_string()
+    }
+
+    /// Logs in with the cookie form, the client keeps the cookie.
+    pub async fn login_form(&self, identifier: &str, password: &str) -> LocalResponse<'_> {
+        let token = self.csrf().await;
+        self.client.post("/api/account/login")
+            .remote(REMOTE.parse().unwrap())
+            .header(ContentType::Form)
+            .body(format!("csrf_token={}&identifier={}&password={}", token, identifier, password))
+            .dispatch().await
+    }
+
+    /// Logs in with json, answers with the bearer access token.
+    pub async fn login_token(&self, identifier: &str, password: &str) -> LocalResponse<'_> {
+        self.client.post("/api/session/token")
+            .remote(REMOTE.parse().unwrap())
+            .json(&serde_json::json!({ "identifier": identifier, "password": password }))
+            .dispatch().await
+    }
+
+    /// The access token of a json login that has to succeed.
+    pub async fn bearer(&self, identifier: &str) -> Header<'static> {
+        let res = self.login_token(identifier, PASSWORD).await;
+        assert_eq!(res.status(), Status::Ok);
+        let body: Value = res.into_json().await.unwrap();
+        Header::new("Authorization", format!("Bearer {}", body["access_token"].as_str().unwrap()))
+    }
+
+    /// A csrf header for requests made with the cookie.
+    pub async fn csrf_header(&self) -> Header<'static> {
+        Header::new(CSRF_HEADER, self.csrf().await)
+    }
+
+    /// Every mail sent to `to` so far, oldest first.
+    pub fn mails(&self, to: &str) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&self.outbox) {
+            Ok(dir) => dir.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+            Err(_) => return vec![],
+        };
+        paths.sort();
+        paths.into_iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .filter(|eml| eml.contains(&format!("To: {}\r\n", to)))
+            .collect()
+    }
+
+    /// The `token` of the link in the latest mail to `to`.
+    pub fn mailed_token(&self, to: &str) -> String {
+        let mails = self.mails(to);
+        let mail = mails.last().expect("a mail was sent");
+        let (_, rest) = mail.split_once("token=").expect("the mail has a link");
+        rest.split_whitespace().next().unwrap().to_string()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.outbox);
+    }
+}
+
+/// The `code` of an error body.
+pub async fn error_code(res: LocalResponse<'_>) -> String {
+    let body: Value = res.into_json().await.unwrap();
+    body["code"].as_str().unwrap_or_default().to_string()
+}
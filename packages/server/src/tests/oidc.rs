@@ -0,0 +1,213 @@
+use rocket::{http::Status, local::asynchronous::LocalResponse, serde::json::{serde_json::{self, json}, Value}};
+
+use crate::{account::{config::AccountConfig, oidc::{ExternalIdentity, IdTokenClaims}}, config::AppConfig};
+
+use super::{error_code, issuer::{MockIssuer, ATTACKER_KEY, CLIENT_ID, ISSUER_KEY}, TestServer, PASSWORD};
+
+const ISSUER: &str = "http://127.0.0.1:9400";
+
+fn claims(sub: &str, email: &str, email_verified: bool) -> IdTokenClaims {
+    serde_json::from_value(json!({ "iss": ISSUER, "sub": sub, "email": email, "email_verified": email_verified })).unwrap()
+}
+
+#[rocket::async_test]
+async fn a_verified_email_reclaims_an_unconfirmed_account() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    // someone registered the email before its owner showed up.
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let acc = server.repos().accounts.find("username", "zeljko").await.unwrap();
+    let cfg = AccountConfig::new(server.repos());
+    cfg.enroll_two_factor(&acc).await.unwrap();
+    server.repos().identities.link(&ExternalIdentity::new("http://elsewhere", "s9", acc.id())).await.unwrap();
+
+    let (linked, created) = cfg.oidc_account(&claims("s1", "Zeljko@example.com", true), server.app()).await.unwrap();
+    assert_eq!(linked.id(), acc.id());
+    assert!(!created);
+    assert_eq!(server.client.get("/api/account/me").header(bearer).dispatch().await.status(), Status::Unauthorized);
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Unauthorized);
+    assert!(server.repos().two_factor.find(acc.id()).await.unwrap().is_none());
+    assert!(server.repos().identities.find("http://elsewhere", "s9").await.unwrap().is_none());
+    assert!(server.repos().identities.find(ISSUER, "s1").await.unwrap().is_some());
+    let profile: Value = server.client.get("/api/account/zeljko").dispatch().await.into_json().await.unwrap();
+    assert_eq!(profile["rank"], "Member");
+}
+
+#[rocket::async_test]
+async fn a_verified_email_links_a_confirmed_account_as_it_is() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    server.register("zeljko").await;
+    let token = server.mailed_token("zeljko@example.com");
+    server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+    let bearer = server.bearer("zeljko").await;
+    let cfg = AccountConfig::new(server.repos());
+
+    cfg.oidc_account(&claims("s1", "zeljko@example.com", true), server.app()).await.unwrap();
+    assert_eq!(server.client.get("/api/account/me").header(bearer).dispatch().await.status(), Status::Ok);
+    assert_eq!(server.login_token("zeljko", PASSWORD).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn an_unverified_email_links_nothing() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let cfg = AccountConfig::new(server.repos());
+
+    assert!(cfg.oidc_account(&claims("s1", "zeljko@example.com", false), server.app()).await.is_err());
+    assert_eq!(server.client.get("/api/account/me").header(bearer).dispatch().await.status(), Status::Ok);
+    assert!(server.repos().identities.find(ISSUER, "s1").await.unwrap().is_none());
+}
+
+/// A server signing in with `issuer`.
+async fn server(issuer: &MockIssuer) -> TestServer {
+    server_with(issuer, |_| {}).await
+}
+
+/// Like [`server`], `change` adjusts the config first.
+async fn server_with(issuer: &MockIssuer, change: impl FnOnce(&mut AppConfig)) -> TestServer {
+    let url = issuer.url.clone();
+    TestServer::with(|config| {
+        config.oidc.enabled = true;
+        config.oidc.issuer = url;
+        config.oidc.client_id = CLIENT_ID.to_string();
+        change(config);
+    }).await
+}
+
+/// Goes through a whole provider login, the provider signs an id token
+/// with `claims` and `key`. Answers with the callback's response.
+async fn provider_login<'c>(server: &'c TestServer, issuer: &MockIssuer, claims: Value, key: &str) -> LocalResponse<'c> {
+    provider_login_with(server, issuer, claims, key, |location| location).await
+}
+
+/// Like [`provider_login`], `change` tampers with the redirect to the
+/// provider first.
+async fn provider_login_with<'c>(server: &'c TestServer, issuer: &MockIssuer, claims: Value, key: &str, change: impl FnOnce(String) -> String) -> LocalResponse<'c> {
+    let res = server.client.get("/api/account/oidc/login").dispatch().await;
+    assert_eq!(res.status(), Status::SeeOther);
+    let location = res.headers().get_one("Location").unwrap().to_string();
+    let callback = issuer.authorize(&change(location), claims, key);
+    server.client.get(callback).dispatch().await
+}
+
+/// The callback refused the login, its message tells `reason`.
+async fn assert_refused(server: &TestServer, res: LocalResponse<'_>, reason: &str) {
+    assert_eq!(res.status(), Status::BadRequest);
+    let body: Value = res.into_json().await.unwrap();
+    assert_eq!(body["code"], "oidc_invalid");
+    assert!(body["message"].as_str().unwrap().contains(reason), "{}", body);
+    assert_eq!(server.client.get("/api/account/me").dispatch().await.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn a_provider_login_creates_an_account() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true, "preferred_username": "new.user" });
+    let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+    assert_eq!(res.status(), Status::SeeOther);
+
+    let res = server.client.get("/api/account/me").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let me: Value = res.into_json().await.unwrap();
+    assert_eq!(me["username"], "new_user");
+    assert_eq!(me["email"], "new@example.com");
+    assert_eq!(me["rank"], "Member");
+}
+
+#[rocket::async_test]
+async fn the_code_needs_the_pkce_verifier() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true });
+    let res = provider_login_with(&server, &issuer, claims, ISSUER_KEY, |location| {
+        let (start, rest) = location.split_once("code_challenge=").unwrap();
+        let rest = rest.split_once('&').map(|(_, rest)| rest).unwrap_or_default();
+        format!("{}code_challenge={}&{}", start, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM", rest)
+    }).await;
+    assert_refused(&server, res, "invalid_grant").await;
+}
+
+#[rocket::async_test]
+async fn id_tokens_need_the_providers_signature() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true });
+    let res = provider_login(&server, &issuer, claims, ATTACKER_KEY).await;
+    assert_refused(&server, res, "InvalidSignature").await;
+}
+
+#[rocket::async_test]
+async fn id_tokens_for_another_client_are_refused() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true, "aud": "someone-else" });
+    let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+    assert_refused(&server, res, "InvalidAudience").await;
+}
+
+#[rocket::async_test]
+async fn id_tokens_of_another_login_are_refused() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true, "nonce": "another-login" });
+    let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+    assert_refused(&server, res, "another login").await;
+}
+
+#[rocket::async_test]
+async fn expired_id_tokens_are_refused() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let an_hour_ago = jsonwebtoken::get_current_timestamp() - 3600;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true, "iat": an_hour_ago - 300, "exp": an_hour_ago });
+    let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+    assert_refused(&server, res, "ExpiredSignature").await;
+}
+
+#[rocket::async_test]
+async fn id_tokens_of_another_issuer_are_refused() {
+    let issuer = MockIssuer::start().await;
+    let server = server(&issuer).await;
+    let claims = json!({ "sub": "s1", "email": "new@example.com", "email_verified": true, "iss": "http://elsewhere" });
+    let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+    assert_refused(&server, res, "InvalidIssuer").await;
+}
+
+#[rocket::async_test]
+async fn a_changed_email_links_once_it_is_confirmed() {
+    for verify_email in [false, true] {
+        let issuer = MockIssuer::start().await;
+        let server = server_with(&issuer, |config| config.verify_email = verify_email).await;
+        server.register("zeljko").await;
+        if verify_email {
+            let token = server.mailed_token("zeljko@example.com");
+            server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+        }
+        let bearer = server.bearer("zeljko").await;
+        let res = server.client.patch("/api/account/me")
+            .header(bearer.clone())
+            .json(&json!({ "email": "victim@example.com", "current_password": PASSWORD }))
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+
+        // whoever typed in the address can't hand the account to its owner.
+        let claims = json!({ "sub": "s1", "email": "victim@example.com", "email_verified": true });
+        let res = provider_login(&server, &issuer, claims.clone(), ISSUER_KEY).await;
+        assert_eq!(error_code(res).await, "email_taken");
+        assert_eq!(server.client.get("/api/account/me").dispatch().await.status(), Status::Unauthorized);
+        assert!(server.repos().identities.find(&issuer.url, "s1").await.unwrap().is_none());
+
+        if !verify_email {
+            let res = server.client.post("/api/account/verify/resend").header(bearer).dispatch().await;
+            assert_eq!(res.status(), Status::Accepted);
+        }
+        let token = server.mailed_token("victim@example.com");
+        server.client.post("/api/account/verify").json(&json!({ "token": token })).dispatch().await;
+        let res = provider_login(&server, &issuer, claims, ISSUER_KEY).await;
+        assert_eq!(res.status(), Status::SeeOther);
+        let me: Value = server.client.get("/api/account/me").dispatch().await.into_json().await.unwrap();
+        assert_eq!(me["username"], "zeljko");
+    }
+}
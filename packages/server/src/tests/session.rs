@@ -0,0 +1,124 @@
+use rocket::{http::{ContentType, Header, Status}, serde::json::{serde_json::json, Value}};
+
+use crate::session::config::Session;
+
+use super::{error_code, TestServer, PASSWORD};
+
+#[rocket::async_test]
+async fn bearer_tokens_refresh() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.login_token("zeljko", PASSWORD).await;
+    let tokens: Value = res.into_json().await.unwrap();
+
+    let res = server.client.post("/api/session/refresh")
+        .json(&json!({ "refresh_token": tokens["refresh_token"] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let refreshed: Value = res.into_json().await.unwrap();
+    assert_ne!(refreshed["access_token"], tokens["access_token"]);
+
+    // the old tokens are gone with the rotation.
+    let old = Header::new("Authorization", format!("Bearer {}", tokens["access_token"].as_str().unwrap()));
+    assert_eq!(server.client.get("/api/account/me").header(old).dispatch().await.status(), Status::Unauthorized);
+    let res = server.client.post("/api/session/refresh")
+        .json(&json!({ "refresh_token": tokens["refresh_token"] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    let fresh = Header::new("Authorization", format!("Bearer {}", refreshed["access_token"].as_str().unwrap()));
+    assert_eq!(server.client.get("/api/account/me").header(fresh).dispatch().await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn sessions_are_stored_by_hash() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.login_token("zeljko", PASSWORD).await;
+    let tokens: Value = res.into_json().await.unwrap();
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let acc = server.repos().accounts.find("username", "zeljko").await.unwrap();
+    let stored = server.repos().sessions.list(acc.id()).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].session_id, Session::hash(access_token));
+    assert!(stored[0].token.is_empty());
+}
+
+#[rocket::async_test]
+async fn sessions_are_listed_and_revoked() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let first = server.bearer("zeljko").await;
+    let second = server.bearer("zeljko").await;
+
+    let res = server.client.get("/api/session").header(first.clone()).dispatch().await;
+    let sessions: Value = res.into_json().await.unwrap();
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().all(|session| session.get("session_id").is_none()));
+    let other = sessions.iter().find(|session| session["current"] == false).unwrap();
+
+    let res = server.client.delete(format!("/api/session/{}", other["id"].as_str().unwrap()))
+        .header(first.clone())
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.client.get("/api/account/me").header(second).dispatch().await.status(), Status::Unauthorized);
+    assert_eq!(server.client.get("/api/account/me").header(first).dispatch().await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn cookie_requests_need_a_csrf_token() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    server.login_form("zeljko", PASSWORD).await;
+    let update = json!({ "username": "zeljko2" });
+
+    let res = server.client.patch("/api/account/me").json(&update).dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "invalid_csrf_token");
+    let res = server.client.patch("/api/account/me")
+        .header(Header::new("X-CSRF-Token", "bm9wZQ"))
+        .json(&update)
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+
+    let res = server.client.patch("/api/account/me").header(server.csrf_header().await).json(&update).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn forms_need_a_csrf_token_without_a_cookie() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let res = server.client.post("/api/account/login")
+        .header(ContentType::Form)
+        .body(format!("identifier=zeljko&password={}", PASSWORD))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "invalid_csrf_token");
+}
+
+#[rocket::async_test]
+async fn csrf_tokens_change_with_the_login() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let anonymous = server.csrf().await;
+    server.login_form("zeljko", PASSWORD).await;
+    let res = server.client.patch("/api/account/me")
+        .header(Header::new("X-CSRF-Token", anonymous))
+        .json(&json!({ "username": "zeljko2" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn bearer_requests_skip_the_csrf_check() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let res = server.client.patch("/api/account/me")
+        .header(bearer)
+        .json(&json!({ "username": "zeljko2" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+}
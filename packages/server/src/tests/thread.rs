@@ -0,0 +1,86 @@
+use rocket::{http::{ContentType, Header, Status}, serde::json::{serde_json::json, Value}};
+
+use super::{error_code, TestServer, PASSWORD};
+
+/// Posts a thread as `bearer`, answers with its id.
+async fn post_thread(server: &TestServer, bearer: &Header<'static>, title: &str) -> String {
+    let res = server.client.post("/api/thread/new")
+        .header(bearer.clone())
+        .header(ContentType::Form)
+        .body(format!("title={}&body=hello+there", title))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Created);
+    let thread: Value = res.into_json().await.unwrap();
+    thread["id"].as_str().unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn threads_are_posted_and_listed() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let id = post_thread(&server, &bearer, "first").await;
+    post_thread(&server, &bearer, "second").await;
+
+    let res = server.client.get(format!("/api/thread/{}", id)).header(ContentType::JSON).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let thread: Value = res.into_json().await.unwrap();
+    assert_eq!(thread["title"], "first");
+
+    let res = server.client.post("/api/thread/retrieve").json(&json!({ "title": "sec" })).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let threads: Value = res.into_json().await.unwrap();
+    assert_eq!(threads.as_array().unwrap().len(), 1);
+}
+
+#[rocket::async_test]
+async fn retrieving_with_the_cookie_takes_the_csrf_token() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    server.login_form("zeljko", PASSWORD).await;
+
+    let res = server.client.post("/api/thread/retrieve").json(&json!({})).dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "invalid_csrf_token");
+    let res = server.client.post("/api/thread/retrieve")
+        .header(server.csrf_header().await)
+        .json(&json!({}))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn unconfirmed_accounts_can_not_post() {
+    let server = TestServer::with(|config| config.verify_email = true).await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let res = server.client.post("/api/thread/new")
+        .header(bearer)
+        .header(ContentType::Form)
+        .body("title=first&body=hello")
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn only_the_author_edits() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    server.register("marko").await;
+    let author = server.bearer("zeljko").await;
+    let other = server.bearer("marko").await;
+    let id = post_thread(&server, &author, "first").await;
+
+    let res = server.client.patch(format!("/api/thread/{}", id))
+        .header(other)
+        .json(&json!({ "title": "mine now" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "missing_permission");
+
+    let res = server.client.patch(format!("/api/thread/{}", id))
+        .header(author)
+        .json(&json!({ "title": "edited" }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+}
@@ -0,0 +1,61 @@
+use rocket::{http::{Header, Status}, serde::json::{serde_json::json, Value}};
+
+use super::{error_code, TestServer};
+
+#[rocket::async_test]
+async fn tokens_are_limited_to_their_scopes() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+
+    let res = server.client.post("/api/token")
+        .header(bearer.clone())
+        .json(&json!({ "name": "ci", "scopes": ["account:read"] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::Created);
+    let created: Value = res.into_json().await.unwrap();
+    let pat = Header::new("Authorization", format!("Bearer {}", created["token"].as_str().unwrap()));
+
+    assert_eq!(server.client.get("/api/account/me").header(pat.clone()).dispatch().await.status(), Status::Ok);
+    let res = server.client.patch("/api/account/me").header(pat.clone()).json(&json!({ "username": "zeljko2" })).dispatch().await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(error_code(res).await, "missing_scope");
+    // tokens can't mint tokens.
+    let res = server.client.get("/api/token").header(pat.clone()).dispatch().await;
+    assert_eq!(error_code(res).await, "session_required");
+
+    let res = server.client.delete(format!("/api/token/{}", created["id"].as_str().unwrap()))
+        .header(bearer)
+        .dispatch().await;
+    assert_eq!(res.status(), Status::NoContent);
+    assert_eq!(server.client.get("/api/account/me").header(pat).dispatch().await.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn tokens_need_a_name_and_scopes() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let res = server.client.post("/api/token")
+        .header(bearer)
+        .json(&json!({ "name": " ", "scopes": [] }))
+        .dispatch().await;
+    assert_eq!(res.status(), Status::BadRequest);
+    let body: Value = res.into_json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["errors"].as_array().unwrap().len(), 2);
+}
+
+#[rocket::async_test]
+async fn token_names_fit_their_column() {
+    let server = TestServer::new().await;
+    server.register("zeljko").await;
+    let bearer = server.bearer("zeljko").await;
+    let create = |name: String| server.client.post("/api/token")
+        .header(bearer.clone())
+        .json(&json!({ "name": name, "scopes": ["account:read"] }));
+    let res = create("a".repeat(256)).dispatch().await;
+    assert_eq!(res.status(), Status::BadRequest);
+    assert_eq!(error_code(res).await, "validation_failed");
+    assert_eq!(create("a".repeat(255)).dispatch().await.status(), Status::Created);
+}
@@ -1,53 +1,145 @@
 use std::time::SystemTime;
 
-use deadpool_postgres::Pool;
+use nanoid::nanoid;
 use postgres_types::{FromSql, ToSql};
-use rocket::{serde::{Serialize, Deserialize}, tokio::time, FromForm};
+use rocket::{serde::{Serialize, Deserialize}, FromForm};
 use std::time::UNIX_EPOCH;
+use tokio_postgres::Row;
 
-use crate::account::config::AccountConfig;
+use crate::{
+    account::{config::{Account, AccountConfig}, enums::Permission},
+    validate::{self, ValidationErrors}
+};
 
+use super::error::ThreadError;
 
+
+/// Saves, finds and lists threads, wherever the
+/// [`Repositories`](crate::repository::Repositories) keep them.
 pub struct ThreadManager {
     thread: Thread
 }
 
 impl ThreadManager {
-    pub async fn save(&self, cfg: AccountConfig<'_>)  {
-        let sql = "select create_thread($1, $2, $3, $4)";
-        let query = cfg.quik_query(sql, &[&self.thread.title, &self.thread.body, &self.thread.created_by, &self.thread.created_on]).await;
-        match query {
+    pub async fn save(&self, cfg: AccountConfig<'_>) -> Result<&Thread, ThreadError> {
+        match cfg.repos.threads.create(&self.thread).await {
             Ok(_) => {
-                println!("[Thread] {} created a post with name {} ", &self.thread.created_by, &self.thread.title());
+                println!("[Thread] {:?} created a post ({}) with name {} ", &self.thread.created_by, &self.thread.id(), &self.thread.title());
+                Ok(&self.thread)
             },
             Err(er) => {
-                println!("[Thread] {} failed to create a post err: {:#?} ", &self.thread.created_by, er.as_db_error());
+                println!("[Thread] {:?} failed to create a post err: {} ", &self.thread.created_by, er);
+                Err(er)
             },
         }
     }
+
+    /// Finds a thread by its id.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use thread::config::ThreadManager;
+    ///
+    /// let thread: Thread = ThreadManager::find(&cfg, "V1StGXR8_Z5jdHi6B-myT").await?;
+    /// ```
+    pub async fn find(cfg: &AccountConfig<'_>, id: &str) -> Result<Thread, ThreadError> {
+        cfg.repos.threads.find(id).await
+    }
+
+    /// Lists the threads matching a [`ThreadFilter`], newest first.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use thread::config::{ThreadManager, ThreadFilter};
+    ///
+    /// let filter = ThreadFilter { created_by: Some("1673919920888240800".to_string()), ..Default::default() };
+    /// let threads: Vec<Thread> = ThreadManager::list(&cfg, &filter).await?;
+    /// ```
+    pub async fn list(cfg: &AccountConfig<'_>, filter: &ThreadFilter) -> Result<Vec<Thread>, ThreadError> {
+        cfg.repos.threads.list(filter).await
+    }
+
+    /// Applies a [`ThreadUpdate`] to the managed thread and records
+    /// `updated_on`. Fields left as `None` are kept as they are.
+    pub async fn update(&mut self, cfg: &AccountConfig<'_>, update: &ThreadUpdate) -> Result<&Thread, ThreadError> {
+        self.thread = cfg.repos.threads.update(&self.thread.id, update, &Thread::now()).await?;
+        Ok(&self.thread)
+    }
+
+    /// Soft deletes the managed thread, it stays stored with
+    /// `deleted_on` set until [`ThreadManager::restore`] is called.
+    pub async fn delete(&self, cfg: &AccountConfig<'_>) -> Result<(), ThreadError> {
+        cfg.repos.threads.delete(&self.thread.id, &Thread::now()).await?;
+        println!("[Thread] {} was deleted", &self.thread.id);
+        Ok(())
+    }
+
+    /// Removes a thread for good.
+    /// Works on soft deleted threads as well.
+    pub async fn purge(cfg: &AccountConfig<'_>, id: &str) -> Result<(), ThreadError> {
+        cfg.repos.threads.purge(id).await?;
+        println!("[Thread] {} was purged", id);
+        Ok(())
+    }
+
+    /// Brings back a soft deleted thread.
+    pub async fn restore(cfg: &AccountConfig<'_>, id: &str) -> Result<Thread, ThreadError> {
+        cfg.repos.threads.restore(id).await
+    }
+}
+
+impl From<Thread> for ThreadManager {
+    fn from(thread: Thread) -> Self {
+        ThreadManager { thread }
+    }
 }
 
-#[derive(Debug, FromForm, ToSql, FromSql)]
+#[derive(Debug, Clone, FromForm, ToSql, FromSql, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub struct Thread {
-    title: String,
-    body: String,
-    #[field(default = "")] 
-    created_by: String,
     #[field(default = "")]
-    created_on: String,
+    #[serde(default)]
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) body: String,
+    /// `None` once the author deleted their account.
+    #[serde(default)]
+    pub(crate) created_by: Option<String>,
+    #[field(default = "")]
+    #[serde(default)]
+    pub(crate) created_on: String,
+    #[serde(default)]
+    pub(crate) updated_on: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub(crate) deleted_on: Option<String>,
 }
 
 impl Thread {
+    /// Checks the title and body (see [`crate::validate`]) before
+    /// anything is stored.
     pub fn new(
-        title: &str, 
-        body: &str, 
+        title: &str,
+        body: &str,
         created_by: &str,
-    ) -> ThreadManager {
-        let mut default = Thread::default();
-        default.title = title.to_string();
-        default.body = body.to_string();
-        default.created_by = created_by.to_string();
-        ThreadManager { thread: default }
+    ) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors
+            .check(validate::thread_title(title))
+            .check(validate::thread_body(body));
+        errors.finish()?;
+
+        Ok(Thread {
+            title: title.to_string(),
+            body: body.to_string(),
+            created_by: Some(created_by.to_string()),
+            ..Default::default()
+        })
+    }
+
+    pub fn id(&self) -> &String {
+        &self.id
     }
 
     pub fn title(&self) -> &String {
@@ -58,23 +150,94 @@ impl Thread {
         &self.body
     }
 
-    pub fn created_on(&self) -> &String {
-        &self.created_on
+    /// The permission `acc` needs to edit or delete this thread.
+    pub fn edit_permission(&self, acc: &Account) -> Permission {
+        if self.created_by.as_ref() == Some(acc.id()) {
+            Permission::EditOwnThread
+        } else {
+            Permission::EditOthersThread
+        }
     }
 
-    pub fn created_by(&self) -> &String {
-        &self.created_by
+    fn now() -> String {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string()
     }
 }
 
 
 impl Default for Thread {
     fn default() -> Self {
-        Self { 
-            title: String::default(), 
-            body: String::default(), 
-            created_by: "".to_string(), 
-            created_on: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string()
+        Self {
+            id: nanoid!(),
+            title: String::default(),
+            body: String::default(),
+            created_by: None,
+            created_on: Thread::now(),
+            updated_on: None,
+            deleted_on: None
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<&Row> for Thread {
+    fn from(value: &Row) -> Self {
+        Thread {
+            id: value.get(0),
+            title: value.get(1),
+            body: value.get(2),
+            created_by: value.get(3),
+            created_on: value.get(4),
+            updated_on: value.get(5),
+            deleted_on: value.get(6)
+        }
+    }
+}
+
+/// The body of `/api/thread/retrieve`, every field is optional.
+///
+/// `title` matches threads whose title contains the given text
+/// (case insensitive).
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ThreadFilter {
+    pub created_by: Option<String>,
+    pub title: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl ThreadFilter {
+    pub const DEFAULT_LIMIT: i64 = 25;
+    pub const MAX_LIMIT: i64 = 100;
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(Self::DEFAULT_LIMIT).clamp(1, Self::MAX_LIMIT)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+/// The body of `PATCH /api/thread/{id}`, fields left out are
+/// not changed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ThreadUpdate {
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+impl ThreadUpdate {
+    /// Checks the fields that are being changed.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(title) = &self.title {
+            errors.check(validate::thread_title(title));
+        }
+        if let Some(body) = &self.body {
+            errors.check(validate::thread_body(body));
+        }
+        errors.finish()
+    }
+}
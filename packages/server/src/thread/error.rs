@@ -0,0 +1,69 @@
+use std::fmt;
+
+use rocket::http::Status;
+
+use crate::db::QueryError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ThreadError {
+    ThreadNotFound(String),
+    InvalidFormat(String),
+    Database(String),
+    Unavailable(String)
+}
+
+
+impl fmt::Display for ThreadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadError::ThreadNotFound(id) => write!(
+                f,
+                "Could not find thread with the id '{}'",
+                id
+            ),
+            ThreadError::InvalidFormat(db_error_message) => write!(
+                f,
+                "{}",
+                db_error_message
+            ),
+            ThreadError::Database(message) => write!(
+                f,
+                "Database error: {}",
+                message
+            ),
+            ThreadError::Unavailable(message) => write!(
+                f,
+                "{}",
+                message
+            ),
+        }
+    }
+}
+
+impl ThreadError {
+    pub fn parse_db_error(er: &QueryError, id: &str) -> ThreadError {
+        if let QueryError::Unavailable(message) = er {
+            return ThreadError::Unavailable(message.clone());
+        }
+        match er.as_db_error() {
+            Some(error) if error.code().code() == "42P16" => {
+                ThreadError::ThreadNotFound(id.to_string())
+            },
+            Some(error) if error.code().code().starts_with("22") || error.code().code().starts_with("23") => {
+                ThreadError::InvalidFormat(error.message().to_string())
+            },
+            Some(error) => ThreadError::Database(error.message().to_string()),
+            None => ThreadError::Database(er.to_string())
+        }
+    }
+
+    /// The http status a route should answer with for this error.
+    pub fn status(&self) -> Status {
+        match self {
+            ThreadError::ThreadNotFound(_) => Status::NotFound,
+            ThreadError::InvalidFormat(_) => Status::BadRequest,
+            ThreadError::Database(_) => Status::InternalServerError,
+            ThreadError::Unavailable(_) => Status::ServiceUnavailable,
+        }
+    }
+}
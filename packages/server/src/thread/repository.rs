@@ -0,0 +1,193 @@
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+
+use crate::{db, repository::SharedStore};
+
+use super::{config::{Thread, ThreadFilter, ThreadUpdate}, error::ThreadError};
+
+/// Stores and looks up threads, see [`crate::repository::Repositories`].
+///
+/// Soft deleted threads are invisible to everything but
+/// [`ThreadRepository::purge`] and [`ThreadRepository::restore`].
+#[async_trait]
+pub trait ThreadRepository: Send + Sync {
+    async fn create(&self, thread: &Thread) -> Result<(), ThreadError>;
+
+    async fn find(&self, id: &str) -> Result<Thread, ThreadError>;
+
+    /// The threads matching `filter`, newest first.
+    async fn list(&self, filter: &ThreadFilter) -> Result<Vec<Thread>, ThreadError>;
+
+    async fn update(&self, id: &str, update: &ThreadUpdate, updated_on: &str) -> Result<Thread, ThreadError>;
+
+    async fn delete(&self, id: &str, deleted_on: &str) -> Result<(), ThreadError>;
+
+    async fn purge(&self, id: &str) -> Result<(), ThreadError>;
+
+    async fn restore(&self, id: &str) -> Result<Thread, ThreadError>;
+}
+
+pub struct PgThreadRepository {
+    pool: Pool
+}
+
+impl PgThreadRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgThreadRepository { pool }
+    }
+}
+
+#[async_trait]
+impl ThreadRepository for PgThreadRepository {
+    async fn create(&self, thread: &Thread) -> Result<(), ThreadError> {
+        let sql = "select create_thread($1, $2, $3, $4, $5)";
+        match db::query(&self.pool, sql, &[&thread.id, &thread.title, &thread.body, &thread.created_by, &thread.created_on]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(ThreadError::parse_db_error(&er, &thread.id)),
+        }
+    }
+
+    async fn find(&self, id: &str) -> Result<Thread, ThreadError> {
+        match db::query(&self.pool, "select * from find_thread_by_id($1)", &[&id]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Thread::from(row)),
+                None => Err(ThreadError::ThreadNotFound(id.to_string()))
+            },
+            Err(er) => Err(ThreadError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn list(&self, filter: &ThreadFilter) -> Result<Vec<Thread>, ThreadError> {
+        let sql = "select * from find_threads($1, $2, $3, $4)";
+        match db::query(&self.pool, sql, &[&filter.created_by, &filter.title, &filter.limit(), &filter.offset()]).await {
+            Ok(res) => Ok(res.iter().map(Thread::from).collect()),
+            Err(er) => Err(ThreadError::parse_db_error(&er, "")),
+        }
+    }
+
+    async fn update(&self, id: &str, update: &ThreadUpdate, updated_on: &str) -> Result<Thread, ThreadError> {
+        let sql = "select * from update_thread($1, $2, $3, $4)";
+        match db::query(&self.pool, sql, &[&id, &update.title, &update.body, &updated_on]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Thread::from(row)),
+                None => Err(ThreadError::ThreadNotFound(id.to_string()))
+            },
+            Err(er) => Err(ThreadError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn delete(&self, id: &str, deleted_on: &str) -> Result<(), ThreadError> {
+        match db::query(&self.pool, "select delete_thread($1, $2)", &[&id, &deleted_on]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(ThreadError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn purge(&self, id: &str) -> Result<(), ThreadError> {
+        match db::query(&self.pool, "select purge_thread($1)", &[&id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(ThreadError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn restore(&self, id: &str) -> Result<Thread, ThreadError> {
+        match db::query(&self.pool, "select * from restore_thread($1)", &[&id]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(Thread::from(row)),
+                None => Err(ThreadError::ThreadNotFound(id.to_string()))
+            },
+            Err(er) => Err(ThreadError::parse_db_error(&er, id)),
+        }
+    }
+}
+
+pub struct MemoryThreadRepository {
+    store: SharedStore
+}
+
+impl MemoryThreadRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryThreadRepository { store }
+    }
+}
+
+#[async_trait]
+impl ThreadRepository for MemoryThreadRepository {
+    async fn create(&self, thread: &Thread) -> Result<(), ThreadError> {
+        self.store.lock().threads.push(thread.clone());
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> Result<Thread, ThreadError> {
+        let store = self.store.lock();
+        match store.threads.iter().find(|thread| thread.id == id && thread.deleted_on.is_none()) {
+            Some(thread) => Ok(thread.clone()),
+            None => Err(ThreadError::ThreadNotFound(id.to_string())),
+        }
+    }
+
+    async fn list(&self, filter: &ThreadFilter) -> Result<Vec<Thread>, ThreadError> {
+        let store = self.store.lock();
+        let title = filter.title.as_ref().map(|title| title.to_lowercase());
+        let mut threads: Vec<&Thread> = store.threads.iter()
+            .filter(|thread| thread.deleted_on.is_none())
+            .filter(|thread| filter.created_by.is_none() || thread.created_by == filter.created_by)
+            .filter(|thread| title.as_ref().is_none_or(|title| thread.title.to_lowercase().contains(title)))
+            .collect();
+        threads.sort_by(|a, b| b.created_on.cmp(&a.created_on));
+        Ok(threads.into_iter()
+            .skip(filter.offset() as usize)
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn update(&self, id: &str, update: &ThreadUpdate, updated_on: &str) -> Result<Thread, ThreadError> {
+        let mut store = self.store.lock();
+        match store.threads.iter_mut().find(|thread| thread.id == id && thread.deleted_on.is_none()) {
+            Some(thread) => {
+                if let Some(title) = &update.title {
+                    thread.title = title.clone();
+                }
+                if let Some(body) = &update.body {
+                    thread.body = body.clone();
+                }
+                thread.updated_on = Some(updated_on.to_string());
+                Ok(thread.clone())
+            },
+            None => Err(ThreadError::ThreadNotFound(id.to_string())),
+        }
+    }
+
+    async fn delete(&self, id: &str, deleted_on: &str) -> Result<(), ThreadError> {
+        let mut store = self.store.lock();
+        match store.threads.iter_mut().find(|thread| thread.id == id && thread.deleted_on.is_none()) {
+            Some(thread) => {
+                thread.deleted_on = Some(deleted_on.to_string());
+                Ok(())
+            },
+            None => Err(ThreadError::ThreadNotFound(id.to_string())),
+        }
+    }
+
+    async fn purge(&self, id: &str) -> Result<(), ThreadError> {
+        let mut store = self.store.lock();
+        let before = store.threads.len();
+        store.threads.retain(|thread| thread.id != id);
+        if store.threads.len() == before {
+            return Err(ThreadError::ThreadNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn restore(&self, id: &str) -> Result<Thread, ThreadError> {
+        let mut store = self.store.lock();
+        match store.threads.iter_mut().find(|thread| thread.id == id && thread.deleted_on.is_some()) {
+            Some(thread) => {
+                thread.deleted_on = None;
+                Ok(thread.clone())
+            },
+            None => Err(ThreadError::ThreadNotFound(id.to_string())),
+        }
+    }
+}
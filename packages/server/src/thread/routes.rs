@@ -1,19 +1,90 @@
-use deadpool_postgres::Pool;
-use rocket::{http::CookieJar, serde::json::Json, State, Route, routes, post, form::Form, response::Redirect, get};
+use rocket::{http::Status, serde::{json::Json, msgpack::MsgPack}, State, Route, routes, post, form::Form, get, patch, delete};
 
-use crate::account::config::AccountConfig;
+use crate::{
+    account::{config::AccountConfig, enums::Permission, guard::{AuthedAccount, RequireRank, rank::Moderator}},
+    error::ApiError,
+    repository::Repositories,
+    token::config::Scope
+};
 
-use super::config::{Thread, ThreadManager};
+use super::config::{Thread, ThreadManager, ThreadFilter, ThreadUpdate};
 
 #[post("/thread/new", data = "<_thread>")]
-pub async fn thread_new(jar: &CookieJar<'_>, _thread: Form<Thread>, pool: &State<Pool>) {
-    let cfg = AccountConfig::new(pool);
-    let find_acc = cfg.find("session", jar.get("sid").unwrap().value()).await.unwrap().clone();
-    let thread = Thread::new(&_thread.title(), &_thread.body(), &find_acc.id());
+pub async fn thread_new(authed: AuthedAccount, _thread: Form<Thread>, repos: &State<Repositories>) -> Result<(Status, Json<Thread>), ApiError> {
+    authed.require_scope(Scope::ThreadWrite)?;
+    authed.require(Permission::CreateThread)?;
+    let cfg = AccountConfig::new(repos);
+    let thread = ThreadManager::from(Thread::new(_thread.title(), _thread.body(), authed.account.id())?);
 
-    thread.save(cfg).await; 
+    let saved = thread.save(cfg).await?;
+    Ok((Status::Created, Json(saved.clone())))
+}
+
+#[get("/thread/<id>", format = "json")]
+pub async fn thread_get(id: &str, repos: &State<Repositories>) -> Result<Json<Thread>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    Ok(Json(ThreadManager::find(&cfg, id).await?))
+}
+
+#[get("/thread/<id>", format = "msgpack", rank = 2)]
+pub async fn thread_get_msgpack(id: &str, repos: &State<Repositories>) -> Result<MsgPack<Thread>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    Ok(MsgPack(ThreadManager::find(&cfg, id).await?))
+}
+
+#[post("/thread/retrieve", format = "json", data = "<filter>")]
+pub async fn thread_retrieve(filter: Json<ThreadFilter>, repos: &State<Repositories>) -> Result<Json<Vec<Thread>>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    Ok(Json(ThreadManager::list(&cfg, &filter).await?))
+}
+
+#[post("/thread/retrieve", format = "msgpack", data = "<filter>", rank = 2)]
+pub async fn thread_retrieve_msgpack(filter: MsgPack<ThreadFilter>, repos: &State<Repositories>) -> Result<MsgPack<Vec<Thread>>, ApiError> {
+    let cfg = AccountConfig::new(repos);
+    Ok(MsgPack(ThreadManager::list(&cfg, &filter).await?))
+}
+
+#[patch("/thread/<id>", format = "json", data = "<update>")]
+pub async fn thread_edit(id: &str, authed: AuthedAccount, update: Json<ThreadUpdate>, repos: &State<Repositories>) -> Result<Json<Thread>, ApiError> {
+    authed.require_scope(Scope::ThreadWrite)?;
+    update.validate()?;
+    let cfg = AccountConfig::new(repos);
+    let thread = ThreadManager::find(&cfg, id).await?;
+    authed.require(thread.edit_permission(&authed.account))?;
+
+    let mut manager = ThreadManager::from(thread);
+    Ok(Json(manager.update(&cfg, &update).await?.clone()))
+}
+
+/// Soft deletes a thread, admins can pass `?purge=true` to
+/// remove it for good.
+#[delete("/thread/<id>?<purge>")]
+pub async fn thread_delete(id: &str, purge: Option<bool>, authed: AuthedAccount, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    authed.require_scope(Scope::ThreadWrite)?;
+    let cfg = AccountConfig::new(repos);
+
+    if purge.unwrap_or(false) {
+        authed.require(Permission::PurgeThread)?;
+        ThreadManager::purge(&cfg, id).await?;
+        return Ok(Status::NoContent);
+    }
+
+    let thread = ThreadManager::find(&cfg, id).await?;
+    authed.require(thread.edit_permission(&authed.account))?;
+    ThreadManager::from(thread).delete(&cfg).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/thread/<id>/restore")]
+pub async fn thread_restore(id: &str, authed: RequireRank<Moderator>, repos: &State<Repositories>) -> Result<Json<Thread>, ApiError> {
+    authed.require_scope(Scope::ThreadWrite)?;
+    let cfg = AccountConfig::new(repos);
+    Ok(Json(ThreadManager::restore(&cfg, id).await?))
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![thread_new]
-}
\ No newline at end of file
+    routes![
+        thread_new, thread_get, thread_get_msgpack, thread_retrieve, thread_retrieve_msgpack,
+        thread_edit, thread_delete, thread_restore
+    ]
+}
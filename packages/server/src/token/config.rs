@@ -0,0 +1,185 @@
+use std::{fmt, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+use nanoid::nanoid;
+use rocket::serde::{Serialize, Deserialize};
+use tokio_postgres::Row;
+
+use crate::{account::config::AccountConfig, session::config::Session};
+
+use super::error::TokenError;
+
+/// What a personal access token is allowed to do. Sessions (cookie or
+/// bearer) can do everything, tokens only what they were given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum Scope {
+    #[serde(rename = "thread:write")]
+    ThreadWrite,
+    #[serde(rename = "account:read")]
+    AccountRead,
+    #[serde(rename = "account:write")]
+    AccountWrite
+}
+
+impl fmt::Display for Scope {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            Scope::ThreadWrite => write!(f, "thread:write"),
+            Scope::AccountRead => write!(f, "account:read"),
+            Scope::AccountWrite => write!(f, "account:write"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = TokenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "thread:write" => Ok(Scope::ThreadWrite),
+            "account:read" => Ok(Scope::AccountRead),
+            "account:write" => Ok(Scope::AccountWrite),
+            _ => Err(TokenError::InvalidScope(s.to_string())),
+        }
+    }
+}
+
+/// A long lived, named and scoped token for scripts (e.g. CI) so they
+/// don't need a real password. Only the sha256 of the token is stored.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccessToken {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub account_id: String,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: String,
+    pub last_used: Option<String>,
+    pub expires_in: Option<String>
+}
+
+impl AccessToken {
+    /// Every personal access token starts with this, so the guard can
+    /// tell them apart from bearer sessions.
+    pub const PREFIX: &'static str = "blog_pat_";
+
+    /// Constructs a new [`AccessToken`] and returns it together with the
+    /// plain token, which is shown to the user once and never again.
+    pub fn new(account_id: &str, name: &str, scopes: Vec<Scope>, expires_in_days: Option<u32>) -> (AccessToken, String) {
+        let now = AccessToken::now();
+        let token = format!("{}{}", AccessToken::PREFIX, nanoid!(40));
+        let acc_token = AccessToken {
+            id: nanoid!(10),
+            account_id: account_id.to_string(),
+            name: name.to_string(),
+            token_hash: Session::hash(&token),
+            scopes,
+            created_at: now.to_string(),
+            last_used: None,
+            expires_in: expires_in_days.map(|days| (now + days as u128 * 86400000).to_string())
+        };
+        (acc_token, token)
+    }
+
+    pub async fn save(&self, cfg: &AccountConfig<'_>) -> Result<(), TokenError> {
+        cfg.repos.tokens.create(self).await?;
+        println!("[Token] {} created a token named {}", &self.account_id, &self.name);
+        Ok(())
+    }
+
+    /// Finds a token by its plain value, expired tokens are not returned.
+    pub async fn find(cfg: &AccountConfig<'_>, token: &str) -> Result<AccessToken, TokenError> {
+        let acc_token = cfg.repos.tokens.find(&Session::hash(token)).await?;
+        if acc_token.is_expired() {
+            return Err(TokenError::TokenNotFound(acc_token.id));
+        }
+        Ok(acc_token)
+    }
+
+    /// Lists the tokens of an account, newest first.
+    pub async fn list(cfg: &AccountConfig<'_>, account_id: &str) -> Result<Vec<AccessToken>, TokenError> {
+        cfg.repos.tokens.list(account_id).await
+    }
+
+    /// Revokes the token with the public `id`, as long as it belongs to
+    /// the account.
+    pub async fn revoke(cfg: &AccountConfig<'_>, account_id: &str, id: &str) -> Result<(), TokenError> {
+        cfg.repos.tokens.revoke(account_id, id).await?;
+        println!("[Token] {} revoked token {}", account_id, id);
+        Ok(())
+    }
+
+    /// Revokes every token of the account, e.g. after its password
+    /// changed.
+    pub async fn remove_all(cfg: &AccountConfig<'_>, account_id: &str) -> Result<(), TokenError> {
+        cfg.repos.tokens.remove_all(account_id).await?;
+        println!("[Token] Revoked all tokens of account ({})", account_id);
+        Ok(())
+    }
+
+    /// Records when the token was last used.
+    pub async fn touch(&mut self, cfg: &AccountConfig<'_>) {
+        let now = AccessToken::now().to_string();
+        if let Err(er) = cfg.repos.tokens.touch(&self.id, &now).await {
+            println!("[Token] Could not update last used: {}", er);
+        }
+        self.last_used = Some(now);
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    fn is_expired(&self) -> bool {
+        match &self.expires_in {
+            Some(expires_in) => expires_in.parse::<u128>().map_or(true, |expires_in| expires_in <= AccessToken::now()),
+            None => false,
+        }
+    }
+
+    fn now() -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+    }
+}
+
+impl From<&Row> for AccessToken {
+    fn from(value: &Row) -> Self {
+        let scopes: Vec<String> = value.get(4);
+        AccessToken {
+            id: value.get(0),
+            account_id: value.get(1),
+            name: value.get(2),
+            token_hash: value.get(3),
+            scopes: scopes.iter().filter_map(|scope| scope.parse().ok()).collect(),
+            created_at: value.get(5),
+            last_used: value.get(6),
+            expires_in: value.get(7)
+        }
+    }
+}
+
+/// The body of `POST /api/token`.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewAccessToken {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Leave out for a token that never expires.
+    pub expires_in_days: Option<u32>
+}
+
+/// What `POST /api/token` answers with, the only time the plain
+/// token is ever shown.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CreatedAccessToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: AccessToken
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use rocket::http::Status;
+
+use crate::db::QueryError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TokenError {
+    TokenNotFound(String),
+    InvalidScope(String),
+    Database(String),
+    Unavailable(String)
+}
+
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::TokenNotFound(id) => write!(
+                f,
+                "Could not find access token '{}'",
+                id
+            ),
+            TokenError::InvalidScope(scope) => write!(
+                f,
+                "'{}' is not a valid scope.",
+                scope
+            ),
+            TokenError::Database(message) => write!(
+                f,
+                "Database error: {}",
+                message
+            ),
+            TokenError::Unavailable(message) => write!(
+                f,
+                "{}",
+                message
+            ),
+        }
+    }
+}
+
+impl TokenError {
+    pub fn parse_db_error(er: &QueryError, id: &str) -> TokenError {
+        if let QueryError::Unavailable(message) = er {
+            return TokenError::Unavailable(message.clone());
+        }
+        match er.as_db_error() {
+            Some(error) if error.code().code() == "42P17" => {
+                TokenError::TokenNotFound(id.to_string())
+            },
+            Some(error) => TokenError::Database(error.message().to_string()),
+            None => TokenError::Database(er.to_string())
+        }
+    }
+
+    /// The http status a route should answer with for this error.
+    pub fn status(&self) -> Status {
+        match self {
+            TokenError::TokenNotFound(_) => Status::NotFound,
+            TokenError::InvalidScope(_) => Status::BadRequest,
+            TokenError::Database(_) => Status::InternalServerError,
+            TokenError::Unavailable(_) => Status::ServiceUnavailable,
+        }
+    }
+}
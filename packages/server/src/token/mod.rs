@@ -0,0 +1,4 @@
+pub mod config;
+pub mod error;
+pub mod repository;
+pub mod routes;
\ No newline at end of file
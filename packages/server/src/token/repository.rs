@@ -0,0 +1,151 @@
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+
+use crate::{db, repository::SharedStore};
+
+use super::{config::{AccessToken, Scope}, error::TokenError};
+
+/// Stores and looks up personal access tokens, see
+/// [`crate::repository::Repositories`].
+#[async_trait]
+pub trait TokenRepository: Send + Sync {
+    async fn create(&self, token: &AccessToken) -> Result<(), TokenError>;
+
+    /// Finds a token by the hash of its plain value, expired ones included.
+    async fn find(&self, token_hash: &str) -> Result<AccessToken, TokenError>;
+
+    /// The tokens of an account, newest first.
+    async fn list(&self, account_id: &str) -> Result<Vec<AccessToken>, TokenError>;
+
+    /// Deletes the token with the public `id`, as long as it belongs to
+    /// the account.
+    async fn revoke(&self, account_id: &str, id: &str) -> Result<(), TokenError>;
+
+    /// Deletes every token of the account.
+    async fn remove_all(&self, account_id: &str) -> Result<(), TokenError>;
+
+    async fn touch(&self, id: &str, last_used: &str) -> Result<(), TokenError>;
+}
+
+pub struct PgTokenRepository {
+    pool: Pool
+}
+
+impl PgTokenRepository {
+    pub fn new(pool: Pool) -> Self {
+        PgTokenRepository { pool }
+    }
+}
+
+#[async_trait]
+impl TokenRepository for PgTokenRepository {
+    async fn create(&self, token: &AccessToken) -> Result<(), TokenError> {
+        let sql = "select create_access_token($1, $2, $3, $4, $5, $6, $7)";
+        let scopes: Vec<String> = token.scopes.iter().map(Scope::to_string).collect();
+        let response = db::query(&self.pool, sql, &[
+            &token.id, &token.account_id, &token.name, &token.token_hash,
+            &scopes, &token.created_at, &token.expires_in
+        ]).await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(er) => Err(TokenError::parse_db_error(&er, &token.id)),
+        }
+    }
+
+    async fn find(&self, token_hash: &str) -> Result<AccessToken, TokenError> {
+        match db::query(&self.pool, "select * from find_access_token($1)", &[&token_hash]).await {
+            Ok(res) => match res.first() {
+                Some(row) => Ok(AccessToken::from(row)),
+                None => Err(TokenError::TokenNotFound(AccessToken::PREFIX.to_string()))
+            },
+            Err(er) => Err(TokenError::parse_db_error(&er, AccessToken::PREFIX)),
+        }
+    }
+
+    async fn list(&self, account_id: &str) -> Result<Vec<AccessToken>, TokenError> {
+        match db::query(&self.pool, "select * from find_access_tokens($1)", &[&account_id]).await {
+            Ok(res) => Ok(res.iter().map(AccessToken::from).collect()),
+            Err(er) => Err(TokenError::parse_db_error(&er, account_id)),
+        }
+    }
+
+    async fn revoke(&self, account_id: &str, id: &str) -> Result<(), TokenError> {
+        match db::query(&self.pool, "select delete_access_token($1, $2)", &[&account_id, &id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(TokenError::parse_db_error(&er, id)),
+        }
+    }
+
+    async fn remove_all(&self, account_id: &str) -> Result<(), TokenError> {
+        match db::query(&self.pool, "select delete_access_tokens($1)", &[&account_id]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(TokenError::parse_db_error(&er, account_id)),
+        }
+    }
+
+    async fn touch(&self, id: &str, last_used: &str) -> Result<(), TokenError> {
+        match db::query(&self.pool, "select touch_access_token($1, $2)", &[&id, &last_used]).await {
+            Ok(_) => Ok(()),
+            Err(er) => Err(TokenError::parse_db_error(&er, id)),
+        }
+    }
+}
+
+pub struct MemoryTokenRepository {
+    store: SharedStore
+}
+
+impl MemoryTokenRepository {
+    pub fn new(store: SharedStore) -> Self {
+        MemoryTokenRepository { store }
+    }
+}
+
+#[async_trait]
+impl TokenRepository for MemoryTokenRepository {
+    async fn create(&self, token: &AccessToken) -> Result<(), TokenError> {
+        self.store.lock().tokens.push(token.clone());
+        Ok(())
+    }
+
+    async fn find(&self, token_hash: &str) -> Result<AccessToken, TokenError> {
+        let store = self.store.lock();
+        match store.tokens.iter().find(|token| token.token_hash == token_hash) {
+            Some(token) => Ok(token.clone()),
+            None => Err(TokenError::TokenNotFound(AccessToken::PREFIX.to_string())),
+        }
+    }
+
+    async fn list(&self, account_id: &str) -> Result<Vec<AccessToken>, TokenError> {
+        let store = self.store.lock();
+        let mut tokens: Vec<AccessToken> = store.tokens.iter()
+            .filter(|token| token.account_id == account_id)
+            .cloned()
+            .collect();
+        tokens.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(tokens)
+    }
+
+    async fn revoke(&self, account_id: &str, id: &str) -> Result<(), TokenError> {
+        let mut store = self.store.lock();
+        let before = store.tokens.len();
+        store.tokens.retain(|token| !(token.account_id == account_id && token.id == id));
+        if store.tokens.len() == before {
+            return Err(TokenError::TokenNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn remove_all(&self, account_id: &str) -> Result<(), TokenError> {
+        self.store.lock().tokens.retain(|token| token.account_id != account_id);
+        Ok(())
+    }
+
+    async fn touch(&self, id: &str, last_used: &str) -> Result<(), TokenError> {
+        let mut store = self.store.lock();
+        if let Some(token) = store.tokens.iter_mut().find(|token| token.id == id) {
+            token.last_used = Some(last_used.to_string());
+        }
+        Ok(())
+    }
+}
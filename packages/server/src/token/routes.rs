@@ -0,0 +1,44 @@
+use rocket::{http::Status, serde::json::Json, State, Route, routes, get, post, delete};
+
+use crate::{
+    account::{config::AccountConfig, guard::AuthedAccount}, error::ApiError, repository::Repositories,
+    validate::{self, ValidationErrors}
+};
+
+use super::config::{AccessToken, CreatedAccessToken, NewAccessToken};
+
+// Tokens can only be managed from a real session, a token can't
+// mint or revoke tokens.
+
+#[get("/token")]
+pub async fn token_list(authed: AuthedAccount, repos: &State<Repositories>) -> Result<Json<Vec<AccessToken>>, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    Ok(Json(AccessToken::list(&cfg, authed.account.id()).await?))
+}
+
+#[post("/token", format = "json", data = "<new>")]
+pub async fn token_new(authed: AuthedAccount, new: Json<NewAccessToken>, repos: &State<Repositories>) -> Result<(Status, Json<CreatedAccessToken>), ApiError> {
+    authed.session()?;
+    let mut errors = ValidationErrors::default();
+    errors.check(validate::token_name(&new.name));
+    errors.check(validate::token_scopes(&new.scopes));
+    errors.finish()?;
+
+    let cfg = AccountConfig::new(repos);
+    let (info, token) = AccessToken::new(authed.account.id(), new.name.trim(), new.scopes.clone(), new.expires_in_days);
+    info.save(&cfg).await?;
+    Ok((Status::Created, Json(CreatedAccessToken { token, info })))
+}
+
+#[delete("/token/<id>")]
+pub async fn token_revoke(id: &str, authed: AuthedAccount, repos: &State<Repositories>) -> Result<Status, ApiError> {
+    authed.session()?;
+    let cfg = AccountConfig::new(repos);
+    AccessToken::revoke(&cfg, authed.account.id(), id).await?;
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![token_list, token_new, token_revoke]
+}
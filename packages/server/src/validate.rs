@@ -0,0 +1,167 @@
+use std::fmt;
+
+use rocket::serde::Serialize;
+
+use crate::token::config::Scope;
+
+// The username and email rules mirror their domains in
+// migrations/0001_schema.sql, they're checked before any query runs so
+// clients get a message per field instead of a constraint name.
+pub const USERNAME_MIN: usize = 3;
+pub const USERNAME_MAX: usize = 32;
+pub const EMAIL_MAX: usize = 254;
+pub const PASSWORD_MIN: usize = 8;
+/// Long enough for any passphrase, short enough that hashing it stays cheap.
+pub const PASSWORD_MAX: usize = 128;
+pub const TITLE_MAX: usize = 255;
+pub const BODY_MAX: usize = 20000;
+/// The `access_tokens.name` column is a VARCHAR(255).
+pub const TOKEN_NAME_MAX: usize = 255;
+
+/// What is wrong with a single input.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: &str) -> Self {
+        FieldError { field, message: message.to_string() }
+    }
+}
+
+/// Every [`FieldError`] of a request, so a form can point out all its
+/// problems at once.
+///
+/// # Example
+///
+/// ```rust
+/// let mut errors = ValidationErrors::default();
+/// errors.check(validate::username("z!"));
+/// errors.check(validate::email("zeljko@gmail.com"));
+/// errors.finish()?; // fails with one error for "username"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn check(&mut self, result: Result<(), FieldError>) -> &mut Self {
+        if let Err(er) = result {
+            self.0.push(er);
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [er] => write!(f, "{}", er.message),
+            errors => write!(f, "{} fields are invalid.", errors.len()),
+        }
+    }
+}
+
+/// Letters, numbers and underscores, like the `username` domain.
+pub fn username(username: &str) -> Result<(), FieldError> {
+    let len = username.chars().count();
+    if !(USERNAME_MIN..=USERNAME_MAX).contains(&len) {
+        return Err(FieldError::new("username", &format!(
+            "Usernames must be between {} and {} characters long.", USERNAME_MIN, USERNAME_MAX
+        )));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(FieldError::new("username", "Usernames can only contain letters, numbers and underscores."));
+    }
+    Ok(())
+}
+
+/// `local@domain.tld`, like the `email` domain.
+pub fn email(email: &str) -> Result<(), FieldError> {
+    let invalid = || FieldError::new("email", "Please enter a valid email address.");
+    if email.len() > EMAIL_MAX {
+        return Err(FieldError::new("email", &format!("Emails can be at most {} characters long.", EMAIL_MAX)));
+    }
+    let (local, domain) = email.split_once('@').ok_or_else(invalid)?;
+    let (host, tld) = domain.rsplit_once('.').ok_or_else(invalid)?;
+    let local_ok = !local.is_empty() && local.chars().all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c));
+    let host_ok = !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || ".-".contains(c));
+    let tld_ok = tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic());
+    if local_ok && host_ok && tld_ok {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// At least [`PASSWORD_MIN`] characters mixing letters with something
+/// else, and not simply the username.
+pub fn password(password: &str, username: &str) -> Result<(), FieldError> {
+    let len = password.chars().count();
+    if len < PASSWORD_MIN {
+        return Err(FieldError::new("password", &format!("Passwords must be at least {} characters long.", PASSWORD_MIN)));
+    }
+    if len > PASSWORD_MAX {
+        return Err(FieldError::new("password", &format!("Passwords can be at most {} characters long.", PASSWORD_MAX)));
+    }
+    let has_letter = password.chars().any(char::is_alphabetic);
+    let has_other = password.chars().any(|c| !c.is_alphabetic());
+    if !has_letter || !has_other {
+        return Err(FieldError::new("password", "Passwords must mix letters with numbers or symbols."));
+    }
+    if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+        return Err(FieldError::new("password", "Passwords can't contain the username."));
+    }
+    Ok(())
+}
+
+pub fn thread_title(title: &str) -> Result<(), FieldError> {
+    if title.trim().is_empty() {
+        return Err(FieldError::new("title", "Threads need a title."));
+    }
+    if title.chars().count() > TITLE_MAX {
+        return Err(FieldError::new("title", &format!("Titles can be at most {} characters long.", TITLE_MAX)));
+    }
+    Ok(())
+}
+
+pub fn thread_body(body: &str) -> Result<(), FieldError> {
+    if body.trim().is_empty() {
+        return Err(FieldError::new("body", "Threads can't be empty."));
+    }
+    if body.chars().count() > BODY_MAX {
+        return Err(FieldError::new("body", &format!("Threads can be at most {} characters long.", BODY_MAX)));
+    }
+    Ok(())
+}
+
+pub fn token_name(name: &str) -> Result<(), FieldError> {
+    if name.trim().is_empty() {
+        return Err(FieldError::new("name", "The token needs a name."));
+    }
+    if name.trim().chars().count() > TOKEN_NAME_MAX {
+        return Err(FieldError::new("name", &format!("Token names can be at most {} characters long.", TOKEN_NAME_MAX)));
+    }
+    Ok(())
+}
+
+pub fn token_scopes(scopes: &[Scope]) -> Result<(), FieldError> {
+    if scopes.is_empty() {
+        return Err(FieldError::new("scopes", "The token needs at least one scope."));
+    }
+    Ok(())
+}